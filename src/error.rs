@@ -0,0 +1,133 @@
+/*
+ * Driver errors.
+ *
+ * A failed vendor command surfaces as an errno from usbnet, which on its own doesn't
+ * say which of the dozens of register accesses in a bind or reset went wrong.
+ * DriverError keeps the command along with the errno until a callback hands it back
+ * to the kernel, where it's logged against the device and turned into the errno.
+ */
+
+use core::fmt;
+
+use linux_kernel_module::c_types::c_int;
+use linux_kernel_module::Error;
+
+use crate::net::UsbNet;
+use crate::{AX_ACCESS_EEPROM, AX_ACCESS_EFUSE, AX_ACCESS_MAC, AX_ACCESS_PHY};
+
+pub(crate) type DriverResult<T> = Result<T, DriverError>;
+
+pub(crate) enum DriverError {
+    /// A vendor read, `cmd` with `value` and `index` as passed to usbnet, failed.
+    Read {
+        cmd: u8,
+        value: u16,
+        index: u16,
+        errno: Error,
+    },
+    /// A vendor write failed.
+    Write {
+        cmd: u8,
+        value: u16,
+        index: u16,
+        errno: Error,
+    },
+    /// Anything else, from the kernel or the driver's own checks.
+    Kernel(Error),
+}
+
+impl DriverError {
+    pub(crate) fn errno(&self) -> &Error {
+        match self {
+            DriverError::Read { errno, .. } | DriverError::Write { errno, .. } => errno,
+            DriverError::Kernel(errno) => errno,
+        }
+    }
+
+    pub(crate) fn to_kernel_errno(&self) -> c_int {
+        self.errno().to_kernel_errno()
+    }
+
+    /// Logs the error against `dev`. `op` names what failed.
+    pub(crate) fn log(&self, dev: &UsbNet, op: &str) {
+        netdev_err!(*dev, "{op} failed: {self}");
+    }
+
+    /// As `log`, for failures the driver carries on from.
+    pub(crate) fn warn(&self, dev: &UsbNet, op: &str) {
+        netdev_warn!(*dev, "{op} failed: {self}");
+    }
+}
+
+impl From<Error> for DriverError {
+    fn from(e: Error) -> Self {
+        DriverError::Kernel(e)
+    }
+}
+
+impl From<DriverError> for Error {
+    fn from(e: DriverError) -> Self {
+        Error::from_kernel_errno(e.to_kernel_errno())
+    }
+}
+
+/// What a vendor command addresses, going by how the AX_ACCESS_* commands use
+/// value and index.
+struct Target {
+    cmd: u8,
+    value: u16,
+    index: u16,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Target { cmd, value, index } = *self;
+
+        match cmd {
+            // index is the register width
+            AX_ACCESS_MAC => write!(f, "MAC register {value:#04x}"),
+            AX_ACCESS_PHY => write!(f, "PHY {value} register {index:#04x}"),
+            AX_ACCESS_EEPROM => write!(f, "EEPROM word {value:#04x}"),
+            AX_ACCESS_EFUSE => write!(f, "eFuse word {value:#04x}"),
+            _ => write!(f, "command {cmd:#04x} ({value:#06x}, {index:#06x})"),
+        }
+    }
+}
+
+impl fmt::Display for DriverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (op, cmd, value, index) = match *self {
+            DriverError::Read {
+                cmd, value, index, ..
+            } => ("reading", cmd, value, index),
+            DriverError::Write {
+                cmd, value, index, ..
+            } => ("writing", cmd, value, index),
+            DriverError::Kernel(ref errno) => return write!(f, "{}", errno.to_kernel_errno()),
+        };
+
+        write!(
+            f,
+            "{op} {}: {}",
+            Target { cmd, value, index },
+            self.to_kernel_errno()
+        )
+    }
+}
+
+pub(crate) trait DriverResultExt {
+    /// The errno for a callback to return, logging a failure as `DriverError::log`.
+    fn into_errno_logged(self, dev: &UsbNet, op: &str) -> c_int;
+}
+
+impl DriverResultExt for DriverResult<()> {
+    fn into_errno_logged(self, dev: &UsbNet, op: &str) -> c_int {
+        match self {
+            Ok(()) => 0,
+            Err(e) => {
+                e.log(dev, op);
+                e.to_kernel_errno()
+            }
+        }
+    }
+}
@@ -0,0 +1,341 @@
+/*
+ * ethtool operations for ax88179_178a.
+ *
+ * usbnet installs its own ethtool_ops before calling bind; these replace them, so the
+ * generic usbnet/MII handlers are carried over alongside the AX88179 specific ones.
+ */
+
+use core::mem::{size_of, transmute};
+use core::sync::atomic::Ordering;
+
+use linux_kernel_module::bindings::{
+    ethtool_eee, ethtool_eeprom, ethtool_ops, ethtool_phys_id_state,
+    ethtool_phys_id_state_ETHTOOL_ID_ACTIVE, ethtool_phys_id_state_ETHTOOL_ID_INACTIVE,
+    ethtool_phys_id_state_ETHTOOL_ID_OFF, ethtool_phys_id_state_ETHTOOL_ID_ON,
+    ethtool_stringset_ETH_SS_PRIV_FLAGS, mii_nway_restart, net_device, usbnet_get_drvinfo,
+    usbnet_get_link, usbnet_get_link_ksettings, usbnet_get_msglevel, usbnet_nway_reset,
+    usbnet_set_link_ksettings, usbnet_set_msglevel, ETH_GSTRING_LEN,
+};
+use linux_kernel_module::c_types::c_int;
+use linux_kernel_module::Error;
+
+use crate::error::{DriverResult, DriverResultExt};
+use crate::led::{LED_IDENTIFY_OFF, LED_IDENTIFY_ON};
+use crate::net::{NetDevice, UsbNet};
+use crate::phy::{Phy, PHY_PHYSR};
+use crate::{
+    ax88179_eee_setting, ax88179_gether_setting, ax88179_led_phy_setting, ax88179_led_setting,
+    ax88179_read_efuse, ax88179_write_efuse, nvm, Ax88179, KernelResultExt, SyncStatic,
+    AX88179_EEE_ADV, AX88179_EEPROM_MAGIC, GMII_PHY_PHYSR_100, GMII_PHY_PHYSR_FULL,
+    GMII_PHY_PHYSR_GIGA, GMII_PHY_PHYSR_LINK, GMII_PHY_PHYSR_SMASK, MDIO_AN_EEE_ADV,
+    MDIO_AN_EEE_LPABLE, MDIO_EEE_1000T, MDIO_EEE_100TX, MDIO_MMD_AN, MDIO_MMD_PCS,
+    MDIO_PCS_EEE_ABLE,
+};
+
+// Legacy ethtool link mode bits, the SUPPORTED_* macros are not visible to bindgen
+const SUPPORTED_100BASET_FULL: u32 = 1 << 3;
+const SUPPORTED_1000BASET_FULL: u32 = 1 << 5;
+
+const ETHTOOL_OPS_ZEROED: ethtool_ops = unsafe { transmute([0u8; size_of::<ethtool_ops>()]) };
+
+/// Converts an EEE capability / advertisement MMD register to ethtool link modes.
+fn mmd_eee_to_ethtool(eee: u16) -> u32 {
+    let mut modes = 0;
+
+    if eee & MDIO_EEE_100TX != 0 {
+        modes |= SUPPORTED_100BASET_FULL;
+    }
+    if eee & MDIO_EEE_1000T != 0 {
+        modes |= SUPPORTED_1000BASET_FULL;
+    }
+
+    modes
+}
+
+/// Converts ethtool link modes to the EEE MMD register bits.
+fn ethtool_to_mmd_eee(modes: u32) -> u16 {
+    let mut eee = 0;
+
+    if modes & SUPPORTED_100BASET_FULL != 0 {
+        eee |= MDIO_EEE_100TX;
+    }
+    if modes & SUPPORTED_1000BASET_FULL != 0 {
+        eee |= MDIO_EEE_1000T;
+    }
+
+    eee
+}
+
+/// Whether EEE is in use on the link described by the PHY status register `physr`:
+/// both ends have to advertise it for the speed the link came up at, which must be
+/// full duplex.
+fn eee_active(physr: u16, advertised: u16, lp_advertised: u16) -> bool {
+    if physr & GMII_PHY_PHYSR_LINK == 0 || physr & GMII_PHY_PHYSR_FULL == 0 {
+        return false;
+    }
+
+    let mode = match physr & GMII_PHY_PHYSR_SMASK {
+        GMII_PHY_PHYSR_GIGA => MDIO_EEE_1000T,
+        GMII_PHY_PHYSR_100 => MDIO_EEE_100TX,
+        _ => 0,
+    };
+
+    advertised & lp_advertised & mode != 0
+}
+
+unsafe fn try_ax88179_get_eee(dev: &UsbNet, edata: &mut ethtool_eee) -> DriverResult<()> {
+    let data = Ax88179::of(dev.as_ptr());
+    let mut phy = Phy::new(data)?;
+
+    let supported = phy.mmd_read(MDIO_MMD_PCS, MDIO_PCS_EEE_ABLE)?;
+    let advertised = phy.mmd_read(MDIO_MMD_AN, MDIO_AN_EEE_ADV)?;
+    let lp_advertised = phy.mmd_read(MDIO_MMD_AN, MDIO_AN_EEE_LPABLE)?;
+    let physr = phy.read(PHY_PHYSR)?;
+
+    edata.supported = mmd_eee_to_ethtool(supported);
+    edata.advertised = mmd_eee_to_ethtool(advertised);
+    edata.lp_advertised = mmd_eee_to_ethtool(lp_advertised);
+    edata.eee_enabled = data.eee.load(Ordering::Relaxed) as u32;
+    edata.eee_active =
+        (edata.eee_enabled != 0 && eee_active(physr, advertised, lp_advertised)) as u32;
+    edata.tx_lpi_enabled = edata.eee_enabled;
+
+    Ok(())
+}
+
+unsafe extern "C" fn ax88179_get_eee(net: *mut net_device, edata: *mut ethtool_eee) -> c_int {
+    let dev = NetDevice::from_raw(net).usbnet();
+
+    try_ax88179_get_eee(&dev, &mut *edata).into_errno_logged(&dev, "get_eee")
+}
+
+unsafe fn try_ax88179_set_eee(dev: &UsbNet, edata: &ethtool_eee) -> DriverResult<()> {
+    let data = Ax88179::of(dev.as_ptr());
+    let enable = edata.eee_enabled != 0;
+
+    // LPI is always on with EEE and its timer isn't adjustable, as get_eee reports.
+    // ethtool passes the old tx_lpi_enabled back when only toggling EEE, so either
+    // state is fine there.
+    let tx_lpi = edata.tx_lpi_enabled != 0;
+    if (tx_lpi != enable && tx_lpi != data.eee.load(Ordering::Relaxed)) || edata.tx_lpi_timer != 0 {
+        return Err(Error::EOPNOTSUPP.into());
+    }
+
+    if enable {
+        let supported = mmd_eee_to_ethtool(AX88179_EEE_ADV);
+
+        // As phylib, no modes in particular means all the supported ones
+        let advertise = match edata.advertised {
+            0 => AX88179_EEE_ADV,
+            modes if modes & !supported != 0 => return Err(Error::EINVAL.into()),
+            modes => ethtool_to_mmd_eee(modes),
+        };
+        data.eee_adv.store(advertise, Ordering::Relaxed);
+    }
+    data.eee.store(enable, Ordering::Relaxed);
+    ax88179_eee_setting(&mut Phy::new(data)?, data.eee_advertisement())?;
+
+    /* Renegotiate so the link partner sees the new advertisement */
+    mii_nway_restart(&mut (*dev.as_ptr()).mii);
+
+    Ok(())
+}
+
+unsafe extern "C" fn ax88179_set_eee(net: *mut net_device, edata: *mut ethtool_eee) -> c_int {
+    let dev = NetDevice::from_raw(net).usbnet();
+
+    try_ax88179_set_eee(&dev, &*edata).into_kernel_errno()
+}
+
+// Private flags, in bit order, for ethtool --show-priv-flags / --set-priv-flags
+const AX88179_PRIV_FLAGS: [&str; 1] = ["green-ethernet"];
+const AX88179_PRIV_FLAG_GREEN_ETHERNET: u32 = 1 << 0;
+
+unsafe extern "C" fn ax88179_get_sset_count(_net: *mut net_device, sset: c_int) -> c_int {
+    match sset as u32 {
+        ethtool_stringset_ETH_SS_PRIV_FLAGS => AX88179_PRIV_FLAGS.len() as c_int,
+        _ => Error::EOPNOTSUPP.to_kernel_errno(),
+    }
+}
+
+unsafe extern "C" fn ax88179_get_strings(_net: *mut net_device, stringset: u32, buf: *mut u8) {
+    if stringset != ethtool_stringset_ETH_SS_PRIV_FLAGS {
+        return;
+    }
+
+    let len = ETH_GSTRING_LEN as usize;
+    let strings = core::slice::from_raw_parts_mut(buf, AX88179_PRIV_FLAGS.len() * len);
+
+    for (slot, name) in strings.chunks_mut(len).zip(AX88179_PRIV_FLAGS.iter()) {
+        slot[..name.len()].copy_from_slice(name.as_bytes());
+        for b in &mut slot[name.len()..] {
+            *b = 0;
+        }
+    }
+}
+
+unsafe extern "C" fn ax88179_get_priv_flags(net: *mut net_device) -> u32 {
+    let data = Ax88179::of(NetDevice::from_raw(net).usbnet().as_ptr());
+    let mut flags = 0;
+
+    if data.geth.load(Ordering::Relaxed) {
+        flags |= AX88179_PRIV_FLAG_GREEN_ETHERNET;
+    }
+
+    flags
+}
+
+unsafe fn try_ax88179_set_priv_flags(dev: &UsbNet, flags: u32) -> DriverResult<()> {
+    let data = Ax88179::of(dev.as_ptr());
+    let geth = flags & AX88179_PRIV_FLAG_GREEN_ETHERNET != 0;
+
+    // Stored first so that reset and link_reset reapply the new mode even if the
+    // PHY write fails part way
+    data.geth.store(geth, Ordering::Relaxed);
+    ax88179_gether_setting(&mut Phy::new(data)?, geth)
+}
+
+unsafe extern "C" fn ax88179_set_priv_flags(net: *mut net_device, flags: u32) -> c_int {
+    let dev = NetDevice::from_raw(net).usbnet();
+
+    try_ax88179_set_priv_flags(&dev, flags).into_kernel_errno()
+}
+
+// ON/OFF cycles per second while identifying
+const AX88179_PHYS_ID_FREQ: c_int = 2;
+
+unsafe fn try_ax88179_set_phys_id(
+    dev: &UsbNet,
+    state: ethtool_phys_id_state,
+) -> DriverResult<c_int> {
+    let data = Ax88179::of(dev.as_ptr());
+    let mut phy = Phy::new(data)?;
+
+    match state {
+        // Have the ethtool core call back with ON / OFF, the LEDs sit behind USB
+        // control transfers so can't be left blinking by themselves
+        ethtool_phys_id_state_ETHTOOL_ID_ACTIVE => return Ok(AX88179_PHYS_ID_FREQ),
+        ethtool_phys_id_state_ETHTOOL_ID_ON => {
+            ax88179_led_phy_setting(&mut phy, LED_IDENTIFY_ON)?;
+        }
+        ethtool_phys_id_state_ETHTOOL_ID_OFF => {
+            ax88179_led_phy_setting(&mut phy, LED_IDENTIFY_OFF)?;
+        }
+        ethtool_phys_id_state_ETHTOOL_ID_INACTIVE => ax88179_led_setting(&mut phy, data.led_mode)?,
+        _ => return Err(Error::EINVAL.into()),
+    }
+
+    Ok(0)
+}
+
+unsafe extern "C" fn ax88179_set_phys_id(
+    net: *mut net_device,
+    state: ethtool_phys_id_state,
+) -> c_int {
+    let dev = NetDevice::from_raw(net).usbnet();
+
+    match try_ax88179_set_phys_id(&dev, state) {
+        Ok(freq) => freq,
+        Err(e) => e.to_kernel_errno(),
+    }
+}
+
+// ethtool -e/-E work on the 64 byte eFuse rather than the EEPROM. eFuse bits can only
+// be burnt once, so set_eeprom refuses anything but writes into blank bytes which
+// leave a valid image behind.
+unsafe extern "C" fn ax88179_get_eeprom_len(_net: *mut net_device) -> c_int {
+    nvm::EFUSE_LEN as c_int
+}
+
+/// The eFuse bytes covered by an ethtool EEPROM request.
+fn eeprom_range(eeprom: &ethtool_eeprom) -> DriverResult<core::ops::Range<usize>> {
+    let start = eeprom.offset as usize;
+    let end = start + eeprom.len as usize;
+
+    if eeprom.len == 0 || end > nvm::EFUSE_LEN {
+        return Err(Error::EINVAL.into());
+    }
+
+    Ok(start..end)
+}
+
+unsafe fn try_ax88179_get_eeprom(
+    dev: &UsbNet,
+    eeprom: &mut ethtool_eeprom,
+    data: *mut u8,
+) -> DriverResult<()> {
+    let range = eeprom_range(eeprom)?;
+
+    eeprom.magic = AX88179_EEPROM_MAGIC;
+
+    let efuse = ax88179_read_efuse(dev.as_ptr())?;
+    core::slice::from_raw_parts_mut(data, range.len()).copy_from_slice(&efuse[range]);
+
+    Ok(())
+}
+
+unsafe extern "C" fn ax88179_get_eeprom(
+    net: *mut net_device,
+    eeprom: *mut ethtool_eeprom,
+    data: *mut u8,
+) -> c_int {
+    let dev = NetDevice::from_raw(net).usbnet();
+
+    try_ax88179_get_eeprom(&dev, &mut *eeprom, data).into_errno_logged(&dev, "get_eeprom")
+}
+
+unsafe fn try_ax88179_set_eeprom(
+    dev: &UsbNet,
+    eeprom: &ethtool_eeprom,
+    data: *const u8,
+) -> DriverResult<()> {
+    if eeprom.magic != AX88179_EEPROM_MAGIC {
+        return Err(Error::EINVAL.into());
+    }
+
+    let range = eeprom_range(eeprom)?;
+    let data = core::slice::from_raw_parts(data, range.len());
+
+    let current = ax88179_read_efuse(dev.as_ptr())?;
+    let new = match nvm::plan_efuse_write(&current, range.start, data) {
+        Ok(new) => new,
+        Err(e) => {
+            netdev_err!(*dev, "refusing to program eFuse: {e}");
+            return Err(Error::EPERM.into());
+        }
+    };
+
+    netdev_info!(*dev, "programming eFuse bytes {range:?}");
+    ax88179_write_efuse(dev.as_ptr(), &current, &new)
+}
+
+unsafe extern "C" fn ax88179_set_eeprom(
+    net: *mut net_device,
+    eeprom: *mut ethtool_eeprom,
+    data: *mut u8,
+) -> c_int {
+    let dev = NetDevice::from_raw(net).usbnet();
+
+    try_ax88179_set_eeprom(&dev, &*eeprom, data).into_errno_logged(&dev, "set_eeprom")
+}
+
+pub(crate) static AX88179_ETHTOOL_OPS: SyncStatic<ethtool_ops> = SyncStatic(ethtool_ops {
+    get_drvinfo: Some(usbnet_get_drvinfo),
+    get_link: Some(usbnet_get_link),
+    get_msglevel: Some(usbnet_get_msglevel),
+    set_msglevel: Some(usbnet_set_msglevel),
+    nway_reset: Some(usbnet_nway_reset),
+    get_link_ksettings: Some(usbnet_get_link_ksettings),
+    set_link_ksettings: Some(usbnet_set_link_ksettings),
+    get_eee: Some(ax88179_get_eee),
+    set_eee: Some(ax88179_set_eee),
+    get_sset_count: Some(ax88179_get_sset_count),
+    get_strings: Some(ax88179_get_strings),
+    get_priv_flags: Some(ax88179_get_priv_flags),
+    set_priv_flags: Some(ax88179_set_priv_flags),
+    set_phys_id: Some(ax88179_set_phys_id),
+    get_eeprom_len: Some(ax88179_get_eeprom_len),
+    get_eeprom: Some(ax88179_get_eeprom),
+    set_eeprom: Some(ax88179_set_eeprom),
+    ..ETHTOOL_OPS_ZEROED
+});
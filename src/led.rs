@@ -0,0 +1,220 @@
+/*
+ * LED configuration for ax88179_178a.
+ *
+ * The EEPROM / eFuse stores a 16 bit LED mode describing, for each of the three LEDs,
+ * which link speeds light it, whether it blinks on activity and whether it shows full
+ * duplex. Older parts only store a one byte preset instead. Nothing here touches the
+ * device, the register values are computed up front and written by
+ * ax88179_led_setting.
+ */
+
+use crate::{
+    GMII_LED0_ACTIVE, GMII_LED0_LINK_10, GMII_LED0_LINK_100, GMII_LED0_LINK_1000, GMII_LED1_ACTIVE,
+    GMII_LED1_LINK_10, GMII_LED1_LINK_100, GMII_LED1_LINK_1000, GMII_LED2_ACTIVE,
+    GMII_LED2_LINK_10, GMII_LED2_LINK_100, GMII_LED2_LINK_1000, GMII_LED_ACTIVE_MASK,
+    GMII_LED_LINK_MASK, LED0_ACTIVE, LED0_FD, LED0_LINK_10, LED0_LINK_100, LED0_LINK_1000,
+    LED0_USB3_MASK, LED1_ACTIVE, LED1_FD, LED1_LINK_10, LED1_LINK_100, LED1_LINK_1000,
+    LED1_USB3_MASK, LED2_ACTIVE, LED2_FD, LED2_LINK_10, LED2_LINK_100, LED2_LINK_1000,
+    LED2_USB3_MASK, LED_VALID,
+};
+
+// LED mode bit -> PHY register bit
+const LED_ACTIVE_MAP: [(u16, u16); 3] = [
+    (LED0_ACTIVE, GMII_LED0_ACTIVE),
+    (LED1_ACTIVE, GMII_LED1_ACTIVE),
+    (LED2_ACTIVE, GMII_LED2_ACTIVE),
+];
+
+const LED_LINK_MAP: [(u16, u16); 9] = [
+    (LED0_LINK_10, GMII_LED0_LINK_10),
+    (LED1_LINK_10, GMII_LED1_LINK_10),
+    (LED2_LINK_10, GMII_LED2_LINK_10),
+    (LED0_LINK_100, GMII_LED0_LINK_100),
+    (LED1_LINK_100, GMII_LED1_LINK_100),
+    (LED2_LINK_100, GMII_LED2_LINK_100),
+    (LED0_LINK_1000, GMII_LED0_LINK_1000),
+    (LED1_LINK_1000, GMII_LED1_LINK_1000),
+    (LED2_LINK_1000, GMII_LED2_LINK_1000),
+];
+
+/// LED modes alternated between by `ethtool -p`. There's no known way to force an
+/// LED on, so "on" lights every LED at any link speed, and identifying an adapter
+/// needs its cable plugged in.
+pub(crate) const LED_IDENTIFY_ON: u16 = LED0_LINK_10
+    | LED0_LINK_100
+    | LED0_LINK_1000
+    | LED1_LINK_10
+    | LED1_LINK_100
+    | LED1_LINK_1000
+    | LED2_LINK_10
+    | LED2_LINK_100
+    | LED2_LINK_1000
+    | LED_VALID;
+pub(crate) const LED_IDENTIFY_OFF: u16 = LED_VALID;
+
+/// Whether a stored LED mode is usable, as opposed to blank or in the legacy format.
+pub(crate) fn led_mode_valid(ledvalue: u16) -> bool {
+    ledvalue != 0xffff && ledvalue & LED_VALID != 0
+}
+
+/// Converts a legacy one byte LED preset into an LED mode.
+pub(crate) fn convert_old_led(ledmode: u8) -> u16 {
+    match ledmode {
+        0xfe => LED0_ACTIVE | LED1_LINK_1000 | LED2_LINK_100 | LED_VALID,
+        0xfd => LED0_ACTIVE | LED1_LINK_1000 | LED2_LINK_100 | LED2_LINK_10 | LED_VALID,
+        0xfc => {
+            LED0_ACTIVE
+                | LED1_ACTIVE
+                | LED1_LINK_1000
+                | LED2_ACTIVE
+                | LED2_LINK_100
+                | LED2_LINK_10
+                | LED_VALID
+        }
+        // 0xff and anything unknown
+        _ => {
+            LED0_ACTIVE
+                | LED1_LINK_10
+                | LED1_LINK_100
+                | LED1_LINK_1000
+                | LED2_ACTIVE
+                | LED2_LINK_10
+                | LED2_LINK_100
+                | LED2_LINK_1000
+                | LED_VALID
+        }
+    }
+}
+
+/// Values to program for an LED mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LedRegs {
+    /// GMII_LED_ACTIVE on PHY extension page 0x2c
+    pub(crate) active: u16,
+    /// GMII_LED_LINK on PHY extension page 0x2c
+    pub(crate) link: u16,
+    /// MAC register 0x73, the full duplex LED behaviour
+    pub(crate) duplex: u8,
+}
+
+/// Merges `ledvalue` into the current PHY LED registers, keeping their unrelated bits.
+pub(crate) fn led_regs(ledvalue: u16, cur_active: u16, cur_link: u16) -> LedRegs {
+    let mut active = cur_active & GMII_LED_ACTIVE_MASK;
+    let mut link = cur_link & GMII_LED_LINK_MASK;
+
+    for &(led, gmii) in LED_ACTIVE_MAP.iter() {
+        if ledvalue & led != 0 {
+            active |= gmii;
+        }
+    }
+
+    for &(led, gmii) in LED_LINK_MAP.iter() {
+        if ledvalue & led != 0 {
+            link |= gmii;
+        }
+    }
+
+    // Each LED either shows full duplex, or, if it has no function at all, is
+    // left to the USB 3 state
+    let mut duplex = 0;
+    for (i, &(fd, usb3_mask)) in [
+        (LED0_FD, LED0_USB3_MASK),
+        (LED1_FD, LED1_USB3_MASK),
+        (LED2_FD, LED2_USB3_MASK),
+    ]
+    .iter()
+    .enumerate()
+    {
+        if ledvalue & fd != 0 {
+            duplex |= 0x01 << (i * 2);
+        } else if ledvalue & usb3_mask == 0 {
+            duplex |= 0x02 << (i * 2);
+        }
+    }
+
+    LedRegs {
+        active,
+        link,
+        duplex,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Expected values worked out from ax88179_convert_old_led and ax88179_led_setting
+    // in the C driver
+
+    #[test]
+    fn old_presets() {
+        assert_eq!(convert_old_led(0xff), 0xbdc1);
+        assert_eq!(convert_old_led(0xfe), 0x9101);
+        assert_eq!(convert_old_led(0xfd), 0x9901);
+        assert_eq!(convert_old_led(0xfc), 0x9d21);
+        assert_eq!(convert_old_led(0x00), 0xbdc1);
+        assert_eq!(convert_old_led(0x42), 0xbdc1);
+    }
+
+    #[test]
+    fn mode_validity() {
+        assert!(led_mode_valid(0x8000));
+        assert!(led_mode_valid(0xbdc1));
+        assert!(!led_mode_valid(0xffff));
+        assert!(!led_mode_valid(0x3dc1));
+    }
+
+    #[test]
+    fn regs_from_cleared_phy() {
+        let table = [
+            (0xbdc1, 0x50, 0x770, 0x00),
+            (0x9101, 0x10, 0x240, 0x00),
+            (0x9d21, 0x70, 0x340, 0x00),
+            (0x8000, 0x00, 0x000, 0x2a),
+            (0xc218, 0x00, 0x004, 0x15),
+        ];
+
+        for &(ledvalue, active, link, duplex) in table.iter() {
+            assert_eq!(
+                led_regs(ledvalue, 0, 0),
+                LedRegs {
+                    active,
+                    link,
+                    duplex
+                },
+                "LED mode {:#06x}",
+                ledvalue
+            );
+        }
+    }
+
+    #[test]
+    fn regs_keep_unrelated_phy_bits() {
+        let table = [
+            (0xbdc1, 0xffdf, 0xfff8, 0x00),
+            (0x9101, 0xff9f, 0xfac8, 0x00),
+            (0x9d21, 0xffff, 0xfbc8, 0x00),
+            (0x8000, 0xff8f, 0xf888, 0x2a),
+            (0xc218, 0xff8f, 0xf88c, 0x15),
+        ];
+
+        for &(ledvalue, active, link, duplex) in table.iter() {
+            assert_eq!(
+                led_regs(ledvalue, 0xffff, 0xffff),
+                LedRegs {
+                    active,
+                    link,
+                    duplex
+                },
+                "LED mode {:#06x}",
+                ledvalue
+            );
+        }
+    }
+
+    #[test]
+    fn identify_modes() {
+        assert_eq!(led_regs(LED_IDENTIFY_ON, 0, 0).link, 0x777);
+        assert_eq!(led_regs(LED_IDENTIFY_OFF, 0, 0).link, 0);
+    }
+}
@@ -20,29 +20,53 @@
  * Foundation, Inc., 59 Temple Place, Suite 330, Boston, MA  02111-1307  USA
  */
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(alloc_prelude)]
 #![feature(format_args_capture)]
 
 extern crate alloc;
 
 use alloc::prelude::v1::*;
-use core::mem::{transmute, zeroed, MaybeUninit};
+use core::mem::{size_of, transmute};
 use core::prelude::v1::*;
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU16, Ordering};
 
 use linux_kernel_module::bindings::{
-    __kbuild_modname, __this_module, driver_info, gfp_t, msleep, pm_message_t, sk_buff, urb,
+    __kbuild_modname, __msecs_to_jiffies, __this_module, driver_info, eth_platform_get_mac_address,
+    get_random_bytes, gfp_t, jiffies, msleep, net_device, netdev_features_t, pm_message_t, sk_buff,
     usb_deregister, usb_device_id, usb_driver, usb_interface, usb_register_driver, usbnet,
-    usbnet_disconnect, usbnet_get_endpoints, usbnet_probe, usbnet_write_cmd, usbnet_write_cmd_nopm,
-    ETH_ALEN, FLAG_AVOID_UNLINK_URBS, FLAG_ETHER, FLAG_FRAMING_AX, USB_DEVICE_ID_MATCH_DEVICE,
-    USB_DIR_OUT, USB_RECIP_DEVICE, USB_TYPE_VENDOR, usbnet_read_cmd, usbnet_read_cmd_nopm,
-    USB_DIR_IN,
+    usbnet_disconnect, usbnet_get_endpoints, usbnet_probe, usbnet_read_cmd, usbnet_read_cmd_nopm,
+    usbnet_write_cmd, usbnet_write_cmd_nopm, ETH_ALEN, EVENT_LINK_RESET, FLAG_AVOID_UNLINK_URBS,
+    FLAG_ETHER, FLAG_FRAMING_AX, NET_ADDR_RANDOM, USB_DEVICE_ID_MATCH_DEVICE, USB_DIR_IN,
+    USB_DIR_OUT, USB_RECIP_DEVICE, USB_TYPE_VENDOR,
 };
-use linux_kernel_module::c_types::{c_int, c_uchar, c_void};
-use linux_kernel_module::{println, Error, KernelResult};
+use linux_kernel_module::c_types::{c_int, c_long, c_uchar, c_ulong, c_void};
+use linux_kernel_module::{Error, KernelResult};
 
 use nudge::unlikely;
 
+use crate::error::{DriverError, DriverResult, DriverResultExt};
+use crate::minidriver::{MiniDriver, UsbNetDriver};
+use crate::net::{NetDevice, SkBuff, UsbInterface, UsbNet, CHECKSUM_NONE, CHECKSUM_UNNECESSARY};
+use crate::phy::{
+    ExtPage2c, Page3, Page5, Phy, PhyLock, EXT2C_LED_ACTIVE, EXT2C_LED_LINK, PAGE3_GETH_CTRL,
+    PAGE5_GETH_TUNE, PHY_ADVERTISE, PHY_BMCR, PHY_CTRL1000,
+};
+
+#[macro_use]
+mod log;
+#[macro_use]
+mod params;
+mod error;
+mod ethtool;
+mod led;
+mod mac;
+mod minidriver;
+mod net;
+mod nvm;
+mod phy;
+mod sysfs;
+
 #[allow(non_camel_case_types)]
 struct ax88179_178a_module {
     _registration: DriverRegistration,
@@ -50,28 +74,30 @@ struct ax88179_178a_module {
 
 impl linux_kernel_module::KernelModule for ax88179_178a_module {
     fn init() -> linux_kernel_module::KernelResult<Self> {
-        println!("Loading ax88179_178a");
+        pr_info!("loading");
 
         Ok(ax88179_178a_module {
-            _registration: DriverRegistration::new(get_driver_info()),
+            _registration: DriverRegistration::new(get_driver_info(), ax88179_id_table()),
         })
     }
 }
 
 impl Drop for ax88179_178a_module {
     fn drop(&mut self) {
-        println!("Unloading ax88179_178a");
+        pr_info!("unloading");
     }
 }
 
 // Holder on driver information
 struct DriverRegistration {
     inner: Box<usb_driver>,
+    _id_table: Box<[usb_device_id]>,
 }
 
 impl DriverRegistration {
-    pub fn new(driver: usb_driver) -> Self {
+    pub fn new(driver: usb_driver, id_table: Box<[usb_device_id]>) -> Self {
         let mut inner = Box::new(driver);
+        inner.id_table = id_table.as_ptr();
         unsafe {
             usb_register_driver(
                 inner.as_mut() as _,
@@ -79,7 +105,10 @@ impl DriverRegistration {
                 __kbuild_modname.as_ptr() as _,
             );
         }
-        Self { inner }
+        Self {
+            inner,
+            _id_table: id_table,
+        }
     }
 }
 
@@ -94,22 +123,31 @@ impl Drop for DriverRegistration {
 // Never allow access of innards until drop
 unsafe impl Sync for DriverRegistration {}
 
+// Kernel tables full of raw pointers, which are never written after compile time
+#[repr(transparent)]
+struct SyncStatic<T>(T);
+
+unsafe impl<T> Sync for SyncStatic<T> {}
+
 // #define RX_SKB_COPY
 // const DRIVER_VERSION: &[u8; 6] = b"1.20.0";
 const DRIVER_DESCRIPTION: &[u8; 46] = b"ASIX AX88179_178A USB 2.0/3.0 Ethernet Devices";
 const DRIVER_AUTHOR: &[u8; 25] = b"ax88179_178a contributors";
 const DRIVER_LICENSE: &[u8; 3] = b"GPL";
 
-// #define MASK_WAKEUP_EVENT_4_SEC		0x01
+// netdev feature bits, the NETIF_F_* macros are not visible to bindgen
+const NETIF_F_SG: netdev_features_t = 1 << 0;
+
+const MASK_WAKEUP_EVENT_4_SEC: u8 = 0x01;
 // #define MASK_WAKEUP_EVENT_8_SEC		0x02
-// #define MASK_WAKEUP_EVENT_TIMER		MASK_WAKEUP_EVENT_4_SEC
+const MASK_WAKEUP_EVENT_TIMER: u8 = MASK_WAKEUP_EVENT_4_SEC;
 
-// #define AX88179_PHY_ID			0x03
-// #define AX_MCAST_FILTER_SIZE		8
+const AX88179_PHY_ID: u16 = 0x03;
+const AX_MCAST_FILTER_SIZE: usize = 8;
 // #define AX_MAX_MCAST			64
 // #define AX_EEPROM_LEN			0x40
-// #define AX_RX_CHECKSUM			1
-// #define AX_TX_CHECKSUM			2
+const AX_RX_CHECKSUM: u8 = 1;
+const AX_TX_CHECKSUM: u8 = 2;
 
 const AX_BULKIN_24K: u8 = 0x18;
 const AX_ACCESS_MAC: u8 = 0x01;
@@ -122,67 +160,67 @@ const AX_WRITE_EFUSE_EN: u8 = 0x09;
 const AX_WRITE_EFUSE_DIS: u8 = 0x0A;
 const AX_ACCESS_MFAB: u8 = 0x10;
 
-// #define PHYSICAL_LINK_STATUS		0x02
-// 	#define	AX_USB_SS		0x04
-// 	#define	AX_USB_HS		0x02
+const PHYSICAL_LINK_STATUS: u16 = 0x02;
+const AX_USB_SS: u8 = 0x04;
+const AX_USB_HS: u8 = 0x02;
 // 	#define	AX_USB_FS		0x01
 
-// #define GENERAL_STATUS			0x03
-// /* Check AX88179 version. UA1:Bit2 = 0,  UA2:Bit2 = 1 */
-// 	#define	AX_SECLD		0x04
+const GENERAL_STATUS: u16 = 0x03;
+/* Check AX88179 version. UA1:Bit2 = 0,  UA2:Bit2 = 1 */
+const AX_SECLD: u8 = 0x04;
 
-// #define AX_SROM_ADDR			0x07
-// #define AX_SROM_CMD			0x0a
-// 	#define EEP_RD			0x04	/* EEprom read command */
+const AX_SROM_ADDR: u16 = 0x07;
+const AX_SROM_CMD: u16 = 0x0a;
+const EEP_RD: u8 = 0x04; /* EEprom read command */
 // 	#define EEP_WR			0x08	/* EEprom write command */
-// 	#define EEP_BUSY		0x10	/* EEprom access module busy */
-// #define AX_SROM_DATA_LOW		0x08
+const EEP_BUSY: u8 = 0x10; /* EEprom access module busy */
+const AX_SROM_DATA_LOW: u16 = 0x08;
 // #define AX_SROM_DATA_HIGH		0x09
 
-// #define AX_RX_CTL			0x0b
-// 	#define AX_RX_CTL_DROPCRCERR		0x0100 /* Drop CRC error packet */
-// 	#define AX_RX_CTL_IPE			0x0200 /* Enable IP header in receive buffer aligned on 32-bit aligment */
+const AX_RX_CTL: u16 = 0x0b;
+const AX_RX_CTL_DROPCRCERR: u16 = 0x0100; /* Drop CRC error packet */
+const AX_RX_CTL_IPE: u16 = 0x0200; /* Enable IP header in receive buffer aligned on 32-bit aligment */
 // 	#define AX_RX_CTL_TXPADCRC		0x0400 /* checksum value in rx header 3 */
-// 	#define AX_RX_CTL_START			0x0080 /* Ethernet MAC start */
-// 	#define AX_RX_CTL_AP			0x0020 /* Accept physcial address from Multicast array */
+const AX_RX_CTL_START: u16 = 0x0080; /* Ethernet MAC start */
+const AX_RX_CTL_AP: u16 = 0x0020; /* Accept physcial address from Multicast array */
 // 	#define AX_RX_CTL_AM			0x0010 /* Accetp Brocadcast frames*/
-// 	#define AX_RX_CTL_AB			0x0008 /* HW auto-added 8-bytes data when meet USB bulk in transfer boundary (1024/512/64)*/
+const AX_RX_CTL_AB: u16 = 0x0008; /* HW auto-added 8-bytes data when meet USB bulk in transfer boundary (1024/512/64)*/
 // 	#define AX_RX_CTL_HA8B			0x0004
-// 	#define AX_RX_CTL_AMALL			0x0002 /* Accetp all multicast frames */
+const AX_RX_CTL_AMALL: u16 = 0x0002; /* Accetp all multicast frames */
 // 	#define AX_RX_CTL_PRO			0x0001 /* Promiscuous Mode */
-// 	#define AX_RX_CTL_STOP			0x0000 /* Stop MAC */
+const AX_RX_CTL_STOP: u16 = 0x0000; /* Stop MAC */
 const AX_NODE_ID: u16 = 0x10;
-// #define AX_MULTI_FILTER_ARRY		0x16
-
-// #define AX_MEDIUM_STATUS_MODE			0x22
-// 	#define AX_MEDIUM_GIGAMODE	0x01
-// 	#define AX_MEDIUM_FULL_DUPLEX	0x02
-// //	#define AX_MEDIUM_ALWAYS_ONE	0x04
-// 	#define AX_MEDIUM_RXFLOW_CTRLEN	0x10
-// 	#define AX_MEDIUM_TXFLOW_CTRLEN	0x20
-// 	#define AX_MEDIUM_RECEIVE_EN	0x100
-// 	#define AX_MEDIUM_PS		0x200
-// 	#define AX_MEDIUM_JUMBO_EN	0x8040
-
-// #define AX_MONITOR_MODE			0x24
-// 	#define AX_MONITOR_MODE_RWLC		0x02
-// 	#define AX_MONITOR_MODE_RWMP		0x04
+const AX_MULTI_FILTER_ARRY: u16 = 0x16;
+
+const AX_MEDIUM_STATUS_MODE: u16 = 0x22;
+const AX_MEDIUM_GIGAMODE: u16 = 0x01;
+const AX_MEDIUM_FULL_DUPLEX: u16 = 0x02;
+// const AX_MEDIUM_ALWAYS_ONE: u16 = 0x04;
+const AX_MEDIUM_RXFLOW_CTRLEN: u16 = 0x10;
+const AX_MEDIUM_TXFLOW_CTRLEN: u16 = 0x20;
+const AX_MEDIUM_RECEIVE_EN: u16 = 0x100;
+const AX_MEDIUM_PS: u16 = 0x200;
+const AX_MEDIUM_JUMBO_EN: u16 = 0x8040;
+
+const AX_MONITOR_MODE: u16 = 0x24;
+const AX_MONITOR_MODE_RWLC: u8 = 0x02;
+const AX_MONITOR_MODE_RWMP: u8 = 0x04;
 // 	#define AX_MONITOR_MODE_RWWF		0x08
 // 	#define AX_MONITOR_MODE_RW_FLAG		0x10
-// 	#define AX_MONITOR_MODE_PMEPOL		0x20
-// 	#define AX_MONITOR_MODE_PMETYPE		0x40
+const AX_MONITOR_MODE_PMEPOL: u8 = 0x20;
+const AX_MONITOR_MODE_PMETYPE: u8 = 0x40;
 
-// #define AX_GPIO_CTRL			0x25
-// 	#define AX_GPIO_CTRL_GPIO3EN		0x80
-// 	#define AX_GPIO_CTRL_GPIO2EN		0x40
-// 	#define AX_GPIO_CTRL_GPIO1EN		0x20
+const AX_GPIO_CTRL: u16 = 0x25;
+const AX_GPIO_CTRL_GPIO3EN: u8 = 0x80;
+const AX_GPIO_CTRL_GPIO2EN: u8 = 0x40;
+const AX_GPIO_CTRL_GPIO1EN: u8 = 0x20;
 
 const AX_PHYPWR_RSTCTL: u16 = 0x26;
 const AX_PHYPWR_RSTCTL_BZ: u16 = 0x0010;
 const AX_PHYPWR_RSTCTL_IPRL: u16 = 0x0020;
 const AX_PHYPWR_RSTCTL_AUTODETACH: u16 = 0x1000;
 
-// #define AX_RX_BULKIN_QCTRL		0x2e
+const AX_RX_BULKIN_QCTRL: u16 = 0x2e;
 // 	#define AX_RX_BULKIN_QCTRL_TIME		0x01
 // 	#define AX_RX_BULKIN_QCTRL_IFG		0x02
 // 	#define AX_RX_BULKIN_QCTRL_SIZE		0x04
@@ -198,14 +236,14 @@ const AX_CLK_SELECT_ACS: u8 = 0x02;
 const AX_CLK_SELECT_ACSREQ: u8 = 0x10;
 const AX_CLK_SELECT_ULR: u8 = 0x08;
 
-// #define AX_RXCOE_CTL			0x34
-// 	#define AX_RXCOE_IP			0x01
-// 	#define AX_RXCOE_TCP			0x02
-// 	#define AX_RXCOE_UDP			0x04
+const AX_RXCOE_CTL: u16 = 0x34;
+const AX_RXCOE_IP: u8 = 0x01;
+const AX_RXCOE_TCP: u8 = 0x02;
+const AX_RXCOE_UDP: u8 = 0x04;
 // 	#define AX_RXCOE_ICMP			0x08
 // 	#define AX_RXCOE_IGMP			0x10
-// 	#define AX_RXCOE_TCPV6			0x20
-// 	#define AX_RXCOE_UDPV6			0x40
+const AX_RXCOE_TCPV6: u8 = 0x20;
+const AX_RXCOE_UDPV6: u8 = 0x40;
 // 	#define AX_RXCOE_ICMV6			0x80
 
 // #if LINUX_VERSION_CODE > KERNEL_VERSION(2, 6, 22)
@@ -217,14 +255,14 @@ const AX_CLK_SELECT_ULR: u8 = 0x08;
 // 					 AX_RXCOE_UDP)
 // #endif
 
-// #define AX_TXCOE_CTL			0x35
-// 	#define AX_TXCOE_IP			0x01
-// 	#define AX_TXCOE_TCP			0x02
-// 	#define AX_TXCOE_UDP			0x04
+const AX_TXCOE_CTL: u16 = 0x35;
+const AX_TXCOE_IP: u8 = 0x01;
+const AX_TXCOE_TCP: u8 = 0x02;
+const AX_TXCOE_UDP: u8 = 0x04;
 // 	#define AX_TXCOE_ICMP			0x08
 // 	#define AX_TXCOE_IGMP			0x10
-// 	#define AX_TXCOE_TCPV6			0x20
-// 	#define AX_TXCOE_UDPV6			0x40
+const AX_TXCOE_TCPV6: u8 = 0x20;
+const AX_TXCOE_UDPV6: u8 = 0x40;
 // 	#define AX_TXCOE_ICMV6			0x80
 // #if LINUX_VERSION_CODE > KERNEL_VERSION(2, 6, 22)
 // 	#define AX_TXCOE_DEF_CSUM	(AX_TXCOE_TCP   | AX_TXCOE_UDP | \
@@ -233,11 +271,11 @@ const AX_CLK_SELECT_ULR: u8 = 0x08;
 // 	#define AX_TXCOE_DEF_CSUM	(AX_TXCOE_TCP	| AX_TXCOE_UDP)
 // #endif
 
-// #define AX_PAUSE_WATERLVL_HIGH		0x54
-// #define AX_PAUSE_WATERLVL_LOW		0x55
+const AX_PAUSE_WATERLVL_HIGH: u16 = 0x54;
+const AX_PAUSE_WATERLVL_LOW: u16 = 0x55;
 
 // #define AX_EEP_EFUSE_CORRECT		0x00
-// #define AX88179_EEPROM_MAGIC			0x17900b95
+const AX88179_EEPROM_MAGIC: u32 = 0x17900b95;
 
 // /*****************************************************************************/
 // /* GMII register definitions */
@@ -295,15 +333,16 @@ const AX_CLK_SELECT_ULR: u8 = 0x08;
 // #define GMII_PHY_ANER				0x06	/* AN expansion reg */
 // #define GMII_PHY_1000BT_CONTROL			0x09	/* control reg for 1000BT */
 // #define GMII_PHY_1000BT_STATUS			0x0A	/* status reg for 1000BT */
-// #define GMII_PHY_MACR				0x0D
-// #define GMII_PHY_MAADR				0x0E
+const GMII_PHY_MACR: u16 = 0x0D;
+const GMII_PHY_MACR_DATA: u16 = 0x4000; /* data access, no post increment */
+const GMII_PHY_MAADR: u16 = 0x0E;
 
-// #define GMII_PHY_PHYSR				0x11	/* PHY specific status register */
-// 	#define GMII_PHY_PHYSR_SMASK		0xc000
-// 	#define GMII_PHY_PHYSR_GIGA		0x8000
-// 	#define GMII_PHY_PHYSR_100		0x4000
-// 	#define GMII_PHY_PHYSR_FULL		0x2000
-// 	#define GMII_PHY_PHYSR_LINK		0x400
+const GMII_PHY_PHYSR: u16 = 0x11; /* PHY specific status register */
+const GMII_PHY_PHYSR_SMASK: u16 = 0xc000;
+const GMII_PHY_PHYSR_GIGA: u16 = 0x8000;
+const GMII_PHY_PHYSR_100: u16 = 0x4000;
+const GMII_PHY_PHYSR_FULL: u16 = 0x2000;
+const GMII_PHY_PHYSR_LINK: u16 = 0x400;
 
 // /* Bit definitions: 1000BaseT AUX Control */
 // #define GMII_1000_AUX_CTRL_MASTER_SLAVE		0x1000
@@ -319,65 +358,135 @@ const AX_CLK_SELECT_ULR: u8 = 0x08;
 // #define GMII_AUX_SPEED_1000			0x0010
 // #define GMII_AUX_SPEED_100			0x0008
 
-// #define GMII_LED_ACTIVE				0x1a
-// 	#define GMII_LED_ACTIVE_MASK		0xff8f
-// 	#define GMII_LED0_ACTIVE		(1 << 4)
-// 	#define GMII_LED1_ACTIVE		(1 << 5)
-// 	#define GMII_LED2_ACTIVE		(1 << 6)
-
-// #define GMII_LED_LINK				0x1c
-// 	#define GMII_LED_LINK_MASK		0xf888
-// 	#define GMII_LED0_LINK_10		(1 << 0)
-// 	#define GMII_LED0_LINK_100		(1 << 1)
-// 	#define GMII_LED0_LINK_1000		(1 << 2)
-// 	#define GMII_LED1_LINK_10		(1 << 4)
-// 	#define GMII_LED1_LINK_100		(1 << 5)
-// 	#define GMII_LED1_LINK_1000		(1 << 6)
-// 	#define GMII_LED2_LINK_10		(1 << 8)
-// 	#define GMII_LED2_LINK_100		(1 << 9)
-// 	#define GMII_LED2_LINK_1000		(1 << 10)
-
-// 	#define	LED_VALID	(1 << 15) /* UA2 LED Setting */
-// 	#define	LED0_ACTIVE	(1 << 0)
-// 	#define	LED0_LINK_10	(1 << 1)
-// 	#define	LED0_LINK_100	(1 << 2)
-// 	#define	LED0_LINK_1000	(1 << 3)
-// 	#define	LED0_FD		(1 << 4)
-// 	#define LED0_USB3_MASK	0x001f
-
-// 	#define	LED1_ACTIVE	(1 << 5)
-// 	#define	LED1_LINK_10	(1 << 6)
-// 	#define	LED1_LINK_100	(1 << 7)
-// 	#define	LED1_LINK_1000	(1 << 8)
-// 	#define	LED1_FD		(1 << 9)
-// 	#define LED1_USB3_MASK	0x03e0
-
-// 	#define	LED2_ACTIVE	(1 << 10)
-// 	#define	LED2_LINK_1000	(1 << 13)
-// 	#define	LED2_LINK_100	(1 << 12)
-// 	#define	LED2_LINK_10	(1 << 11)
-// 	#define	LED2_FD		(1 << 14)
-// 	#define LED2_USB3_MASK	0x7c00
-
-// #define GMII_PHYPAGE				0x1e
-
-// #define GMII_PHY_PAGE_SELECT			0x1f
-// 	#define GMII_PHY_PAGE_SELECT_EXT	0x0007
-// 	#define GMII_PHY_PAGE_SELECT_PAGE0	0X0000
+const GMII_LED_ACTIVE: u16 = 0x1a;
+const GMII_LED_ACTIVE_MASK: u16 = 0xff8f;
+const GMII_LED0_ACTIVE: u16 = 1 << 4;
+const GMII_LED1_ACTIVE: u16 = 1 << 5;
+const GMII_LED2_ACTIVE: u16 = 1 << 6;
+
+const GMII_LED_LINK: u16 = 0x1c;
+const GMII_LED_LINK_MASK: u16 = 0xf888;
+const GMII_LED0_LINK_10: u16 = 1 << 0;
+const GMII_LED0_LINK_100: u16 = 1 << 1;
+const GMII_LED0_LINK_1000: u16 = 1 << 2;
+const GMII_LED1_LINK_10: u16 = 1 << 4;
+const GMII_LED1_LINK_100: u16 = 1 << 5;
+const GMII_LED1_LINK_1000: u16 = 1 << 6;
+const GMII_LED2_LINK_10: u16 = 1 << 8;
+const GMII_LED2_LINK_100: u16 = 1 << 9;
+const GMII_LED2_LINK_1000: u16 = 1 << 10;
+
+const LED_VALID: u16 = 1 << 15; /* UA2 LED Setting */
+const LED0_ACTIVE: u16 = 1 << 0;
+const LED0_LINK_10: u16 = 1 << 1;
+const LED0_LINK_100: u16 = 1 << 2;
+const LED0_LINK_1000: u16 = 1 << 3;
+const LED0_FD: u16 = 1 << 4;
+const LED0_USB3_MASK: u16 = 0x001f;
+
+const LED1_ACTIVE: u16 = 1 << 5;
+const LED1_LINK_10: u16 = 1 << 6;
+const LED1_LINK_100: u16 = 1 << 7;
+const LED1_LINK_1000: u16 = 1 << 8;
+const LED1_FD: u16 = 1 << 9;
+const LED1_USB3_MASK: u16 = 0x03e0;
+
+const LED2_ACTIVE: u16 = 1 << 10;
+const LED2_LINK_1000: u16 = 1 << 13;
+const LED2_LINK_100: u16 = 1 << 12;
+const LED2_LINK_10: u16 = 1 << 11;
+const LED2_FD: u16 = 1 << 14;
+const LED2_USB3_MASK: u16 = 0x7c00;
+
+const GMII_PHYPAGE: u16 = 0x1e;
+
+const GMII_PHY_PAGE_SELECT: u16 = 0x1f;
+const GMII_PHY_PAGE_SELECT_EXT: u16 = 0x0007;
+const GMII_PHY_PAGE_SELECT_PAGE0: u16 = 0x0000;
 // 	#define GMII_PHY_PAGE_SELECT_PAGE1	0X0001
 // 	#define GMII_PHY_PAGE_SELECT_PAGE2	0X0002
-// 	#define GMII_PHY_PAGE_SELECT_PAGE3	0X0003
+const GMII_PHY_PAGE_SELECT_PAGE3: u16 = 0x0003;
 // 	#define GMII_PHY_PAGE_SELECT_PAGE4	0X0004
-// 	#define GMII_PHY_PAGE_SELECT_PAGE5	0X0005
+const GMII_PHY_PAGE_SELECT_PAGE5: u16 = 0x0005;
 // 	#define GMII_PHY_PAGE_SELECT_PAGE6	0X0006
 
 // /******************************************************************************/
-#[allow(non_camel_case_types)]
-#[derive(Debug)]
-struct ax88179_data {
+/// Per-device state, made in bind and freed after unbind. Together with what the
+/// net_device keeps, like the MAC address, this is everything Ax88179::restore needs
+/// to reprogram the chip.
+struct Ax88179 {
+    dev: *mut usbnet,
+    phy_lock: PhyLock,
+
+    // Fixed at bind: there's no set_rx_mode, set_features or set_wol to change the
+    // RX filter, offloads or wakeup later. Whichever gets added must update these
+    // too, or write_config puts the bind time values back.
     rxctl: u16,
     checksum: u8,
     reg_monitor: c_uchar,
+    multi_filter: [u8; AX_MCAST_FILTER_SIZE],
+    led_mode: u16,
+
+    // Taken by save, for the link settings ethtool writes straight into the PHY
+    bmcr: AtomicU16,
+    advertise: AtomicU16,
+    ctrl1000: AtomicU16,
+
+    // Per-device tuning, seeded from the module parameters and changed through sysfs.
+    // Picked up on the next link_reset.
+    bsize: AtomicI32,
+    ifg: AtomicI32,
+    eee: AtomicBool,
+    // MDIO_EEE_* modes advertised while EEE is enabled, set with ethtool --set-eee
+    eee_adv: AtomicU16,
+    geth: AtomicBool,
+    phy_settings_changed: AtomicBool,
+}
+
+impl Ax88179 {
+    fn new(dev: *mut usbnet) -> Box<Ax88179> {
+        let ax = Box::new(Ax88179 {
+            dev,
+            phy_lock: PhyLock::new(),
+            rxctl: AX_RX_CTL_DEFAULT,
+            checksum: AX_RX_CHECKSUM | AX_TX_CHECKSUM,
+            reg_monitor: AX_MONITOR_MODE_PMETYPE | AX_MONITOR_MODE_PMEPOL | AX_MONITOR_MODE_RWMP,
+            multi_filter: [0; AX_MCAST_FILTER_SIZE],
+            led_mode: 0,
+            bmcr: AtomicU16::new(0),
+            advertise: AtomicU16::new(0),
+            ctrl1000: AtomicU16::new(0),
+            bsize: AtomicI32::new(bsize.get()),
+            ifg: AtomicI32::new(ifg.get()),
+            eee: AtomicBool::new(bEEE.get() != 0),
+            eee_adv: AtomicU16::new(AX88179_EEE_ADV),
+            geth: AtomicBool::new(bGETH.get() != 0),
+            phy_settings_changed: AtomicBool::new(false),
+        });
+
+        unsafe { ax.phy_lock.init() };
+        ax
+    }
+
+    /// The state of a bound device, the only way to reach it.
+    ///
+    /// # Safety
+    /// `dev` must be bound to this driver, and the returned reference must not
+    /// outlive the binding. Everything which changes after bind is an atomic or
+    /// behind the PHY lock, as the usbnet hooks, PM callbacks, ethtool and sysfs all
+    /// reach it concurrently.
+    unsafe fn of<'a>(dev: *mut usbnet) -> &'a Ax88179 {
+        MiniDriver::<Ax88179>::data(dev)
+    }
+
+    /// What to put in the PHY's EEE advertisement register.
+    fn eee_advertisement(&self) -> u16 {
+        if self.eee.load(Ordering::Relaxed) {
+            self.eee_adv.load(Ordering::Relaxed)
+        } else {
+            0
+        }
+    }
 }
 
 // struct ax88179_async_handle {
@@ -387,15 +496,18 @@ struct ax88179_data {
 // } __attribute__ ((packed));
 
 // struct ax88179_int_data {
-// 	__le16 res1;
-// #define AX_INT_PPLS_LINK	(1 << 0)
-// #define AX_INT_SPLS_LINK	(1 << 1)
-// #define AX_INT_CABOFF_UNPLUG	(1 << 7)
+// 	u16 res1;
 // 	u8 link;
-// 	__le16 res2;
+// 	u16 res2;
 // 	u8 status;
-// 	__le16 res3;
+// 	u16 res3;
 // } __attribute__ ((packed));
+const AX_INT_DATA_LEN: usize = 8;
+const AX_INT_DATA_LINK: usize = 2;
+
+const AX_INT_PPLS_LINK: u8 = 1 << 0;
+// #define AX_INT_SPLS_LINK	(1 << 1)
+// #define AX_INT_CABOFF_UNPLUG	(1 << 7)
 
 // #define AX_RXHDR_L4_ERR		(1 << 8)
 // #define AX_RXHDR_L3_ERR		(1 << 9)
@@ -407,14 +519,14 @@ struct ax88179_data {
 // #define AX_RXHDR_L3_TYPE_IP		1
 // #define AX_RXHDR_L3_TYPE_IPV6		2
 
-// #define AX_RXHDR_L4_TYPE_MASK			0x1c
-// #define AX_RXHDR_L4_TYPE_UDP			4
-// #define AX_RXHDR_L4_TYPE_TCP			16
-// #define AX_RXHDR_L3CSUM_ERR			2
-// #define AX_RXHDR_L4CSUM_ERR			1
-// #define AX_RXHDR_CRC_ERR			0x20000000
+const AX_RXHDR_L4_TYPE_MASK: u32 = 0x1c;
+const AX_RXHDR_L4_TYPE_UDP: u32 = 4;
+const AX_RXHDR_L4_TYPE_TCP: u32 = 16;
+const AX_RXHDR_L3CSUM_ERR: u32 = 2;
+const AX_RXHDR_L4CSUM_ERR: u32 = 1;
+const AX_RXHDR_CRC_ERR: u32 = 0x20000000;
 // #define AX_RXHDR_MII_ERR			0x40000000
-// #define AX_RXHDR_DROP_ERR			0x80000000
+const AX_RXHDR_DROP_ERR: u32 = 0x80000000;
 // #if 0
 // struct ax88179_rx_pkt_header {
 
@@ -436,159 +548,250 @@ struct ax88179_data {
 
 // } __attribute__ ((packed));
 // #endif
-// static struct {unsigned char ctrl, timer_l, timer_h, size, ifg; }
-// AX88179_BULKIN_SIZE[] =	{
-// 	{7, 0x4f, 0,	0x12, 0xff},
-// 	{7, 0x20, 3,	0x16, 0xff},
-// 	{7, 0xae, 7,	0x18, 0xff},
-// 	{7, 0xcc, 0x4c, 0x18, 8},
-// };
+/// Contents of the five RX bulk-in queue registers starting at AX_RX_BULKIN_QCTRL
+#[derive(Debug, Clone, Copy)]
+struct BulkInConfig {
+    ctrl: u8,
+    timer_l: u8,
+    timer_h: u8,
+    size: u8,
+    ifg: u8,
+}
+
+impl BulkInConfig {
+    /// Applies the `bsize` and `ifg` module parameter overrides, -1 keeps the default.
+    fn with_overrides(mut self, bsize: c_int, ifg: c_int) -> Self {
+        if bsize != -1 {
+            if bsize == 0 {
+                self.timer_l = 0;
+                self.timer_h = 0;
+            }
+            self.size = bsize as u8;
+        }
+
+        if ifg != -1 {
+            self.ifg = ifg as u8;
+        }
+
+        self
+    }
+
+    fn to_bytes(self) -> [u8; 5] {
+        [self.ctrl, self.timer_l, self.timer_h, self.size, self.ifg]
+    }
+
+    /// The URB size which fits a full bulk-in queue.
+    fn rx_urb_size(self) -> usize {
+        1024 * (self.size as usize + 2)
+    }
+}
+
+const AX88179_BULKIN_SIZE: [BulkInConfig; 4] = [
+    BulkInConfig {
+        ctrl: 7,
+        timer_l: 0x4f,
+        timer_h: 0,
+        size: 0x12,
+        ifg: 0xff,
+    },
+    BulkInConfig {
+        ctrl: 7,
+        timer_l: 0x20,
+        timer_h: 3,
+        size: 0x16,
+        ifg: 0xff,
+    },
+    BulkInConfig {
+        ctrl: 7,
+        timer_l: 0xae,
+        timer_h: 7,
+        size: 0x18,
+        ifg: 0xff,
+    },
+    BulkInConfig {
+        ctrl: 7,
+        timer_l: 0xcc,
+        timer_h: 0x4c,
+        size: 0x18,
+        ifg: 8,
+    },
+];
 
 // static int ax88179_reset(struct usbnet *dev);
 // static int ax88179_link_reset(struct usbnet *dev);
-// static int ax88179_AutoDetach(struct usbnet *dev, int in_pm);
 
 // static char version[] =
 // KERN_INFO "ASIX USB Ethernet Adapter:v" DRIVER_VERSION
 // //	" " __TIME__ " " __DATE__ "\n"
 // "		http://www.asix.com.tw\n";
 
-// static int msg_enable;
-// module_param(msg_enable, int, 0);
-// MODULE_PARM_DESC(msg_enable, "usbnet msg_enable");
-
-// static int bsize = -1;
-// module_param(bsize, int, 0);
-// MODULE_PARM_DESC(bsize, "RX Bulk IN Queue Size");
-
-// static int ifg = -1;
-// module_param(ifg, int, 0);
-// MODULE_PARM_DESC(ifg, "RX Bulk IN Inter Frame Gap");
+module_param!(msg_enable = 0, -1..=0x7fff, "usbnet msg_enable");
 
-// /* EEE advertisement is disabled in default setting */
-// static int bEEE = 0;
-// module_param(bEEE, int, 0);
-// MODULE_PARM_DESC(bEEE, "EEE advertisement configuration");
+module_param!(bsize = -1, -1..=24, "RX Bulk IN Queue Size");
 
-// /* Green ethernet advertisement is disabled in default setting */
-// static int bGETH = 0;
-// module_param(bGETH, int, 0);
-// MODULE_PARM_DESC(bGETH, "Green ethernet configuration");
+module_param!(ifg = -1, -1..=255, "RX Bulk IN Inter Frame Gap");
 
-/* ASIX AX88179/178A based USB 3.0/2.0 Gigabit Ethernet Devices */
-unsafe fn __ax88179_read_cmd(dev: *mut usbnet, cmd: u8, value: u16, index: u16, size: u16, data: *mut c_void, in_pm: c_int) -> KernelResult<()>
-{
-    assert!(!dev.is_null());
-
-	let f = if in_pm == 0 {
-		usbnet_read_cmd
-    } else {
-        usbnet_read_cmd_nopm
-    };
+/* EEE advertisement is disabled in default setting */
+module_param!(bEEE = 0, 0..=1, "EEE advertisement configuration");
 
-	let ret = f(dev, cmd, (USB_DIR_IN | USB_TYPE_VENDOR | USB_RECIP_DEVICE) as u8, value, index, data, size);
+/* Green ethernet advertisement is disabled in default setting */
+module_param!(bGETH = 0, 0..=1, "Green ethernet configuration");
 
-	if unlikely(ret < 0) {
-        // netdev_warn(dev->net, "Failed to read reg index 0x%04x: %d\n", index, ret);
-        println!("WARNING: ax88179 - failed to read reg index {index:#x}: {ret}");
-    }
+/* A random MAC replacing an invalid one is only kept until unplug by default */
+module_param!(
+    persist_mac = 0,
+    0..=1,
+    "Write a generated MAC address back to the EEPROM"
+);
 
-	KernelResult::from_kernel_errno(ret)
-}
+/* ASIX AX88179/178A based USB 3.0/2.0 Gigabit Ethernet Devices */
 
-unsafe fn __ax88179_write_cmd(
+/// Issues the adapter's vendor control transfers.
+///
+/// Transfers normally resume the device first. From within the USB PM callbacks the
+/// device is already being suspended or resumed, so they must use the _nopm variants
+/// instead. CmdCtx::nopm makes a context which routes to those, CmdCtx::new one
+/// which doesn't; they are the only way to issue a transfer.
+#[derive(Clone, Copy)]
+struct CmdCtx {
     dev: *mut usbnet,
-    cmd: u8,
-    value: u16,
-    index: u16,
-    size: u16,
-    data: *mut c_void,
-    in_pm: c_int,
-) -> KernelResult<()> {
-    assert!(!dev.is_null());
-
-    let f = if in_pm == 0 {
-        usbnet_write_cmd
-    } else {
-        usbnet_write_cmd_nopm
-    };
+    in_pm: bool,
+}
 
-    let ret = f(
-        dev,
-        cmd,
-        (USB_DIR_OUT | USB_TYPE_VENDOR | USB_RECIP_DEVICE) as u8,
-        value,
-        index,
-        data,
-        size,
-    );
+impl CmdCtx {
+    /// Fails with ENODEV if there's no device, as for an interface usbnet isn't
+    /// bound to.
+    unsafe fn new(dev: *mut usbnet) -> DriverResult<Self> {
+        if dev.is_null() {
+            return Err(Error::ENODEV.into());
+        }
 
-    if unlikely(ret < 0) {
-        // netdev_warn(dev->net, "Failed to write reg index 0x%04x: %d\n", index, ret);
-        println!("WARNING: ax88179 - failed to write reg index {index:#x}: {ret}");
+        Ok(Self { dev, in_pm: false })
     }
 
-    KernelResult::from_kernel_errno(ret)
-}
-
-// static int ax88179_read_cmd_nopm(struct usbnet *dev, u8 cmd, u16 value,
-// 				 u16 index, u16 size, void *data, int eflag)
-// {
-// 	int ret;
+    /// # Safety
+    /// Only for the device of the interface handed to suspend, resume or
+    /// reset_resume, for the duration of that callback.
+    unsafe fn nopm(dev: &UsbNet) -> Self {
+        Self {
+            dev: dev.as_ptr(),
+            in_pm: true,
+        }
+    }
 
-// 	if (eflag && (2 == size)) {
-// 		u16 buf = 0;
-// 		ret = __ax88179_read_cmd(dev, cmd, value, index, size, &buf, 1);
-// 		le16_to_cpus(&buf);
-// 		*((u16 *)data) = buf;
-// 	} else if (eflag && (4 == size)) {
-// 		u32 buf = 0;
-// 		ret = __ax88179_read_cmd(dev, cmd, value, index, size, &buf, 1);
-// 		le32_to_cpus(&buf);
-// 		*((u32 *)data) = buf;
-// 	} else {
-// 		ret = __ax88179_read_cmd(dev, cmd, value, index, size, data, 1);
-// 	}
+    fn usbnet(&self) -> UsbNet {
+        unsafe { UsbNet::from_raw(self.dev) }
+    }
 
-// 	return ret;
-// }
+    unsafe fn __read(
+        &self,
+        cmd: u8,
+        value: u16,
+        index: u16,
+        size: u16,
+        data: *mut c_void,
+    ) -> DriverResult<()> {
+        let f = if self.in_pm {
+            usbnet_read_cmd_nopm
+        } else {
+            usbnet_read_cmd
+        };
+
+        let ret = f(
+            self.dev,
+            cmd,
+            (USB_DIR_IN | USB_TYPE_VENDOR | USB_RECIP_DEVICE) as u8,
+            value,
+            index,
+            data,
+            size,
+        );
+
+        if unlikely(ret < 0) {
+            return Err(DriverError::Read {
+                cmd,
+                value,
+                index,
+                errno: Error::from_kernel_errno(ret),
+            });
+        }
 
-// static int ax88179_write_cmd_nopm(struct usbnet *dev, u8 cmd, u16 value,
-// 				  u16 index, u16 size, void *data)
-// {
-// 	int ret;
+        // usbnet returns the number of bytes transferred on success
+        Ok(())
+    }
 
-// 	if (2 == size) {
-// 		u16 buf = 0;
-// 		buf = *((u16 *)data);
-// 		cpu_to_le16s(&buf);
-// 		ret = __ax88179_write_cmd(dev, cmd, value, index,
-// 					  size, &buf, 1);
-// 	} else {
-// 		ret = __ax88179_write_cmd(dev, cmd, value, index,
-// 					  size, data, 1);
-// 	}
+    unsafe fn __write(
+        &self,
+        cmd: u8,
+        value: u16,
+        index: u16,
+        size: u16,
+        data: *mut c_void,
+    ) -> DriverResult<()> {
+        let f = if self.in_pm {
+            usbnet_write_cmd_nopm
+        } else {
+            usbnet_write_cmd
+        };
+
+        let ret = f(
+            self.dev,
+            cmd,
+            (USB_DIR_OUT | USB_TYPE_VENDOR | USB_RECIP_DEVICE) as u8,
+            value,
+            index,
+            data,
+            size,
+        );
+
+        if unlikely(ret < 0) {
+            return Err(DriverError::Write {
+                cmd,
+                value,
+                index,
+                errno: Error::from_kernel_errno(ret),
+            });
+        }
 
-// 	return ret;
-// }
+        // usbnet returns the number of bytes transferred on success
+        Ok(())
+    }
 
-unsafe fn ax88179_read_cmd(dev: *mut usbnet, cmd: u8, value: u16, index: u16, size: u16, data: *mut c_void, eflag: c_int) -> KernelResult<()>
-{
-	let result;
+    /// Reads `size` bytes into `data`. With `eflag` set, 2 and 4 byte values are
+    /// converted from little endian.
+    unsafe fn read(
+        &self,
+        cmd: u8,
+        value: u16,
+        index: u16,
+        size: u16,
+        data: *mut c_void,
+        eflag: c_int,
+    ) -> DriverResult<()> {
+        let result;
+
+        if (eflag != 0) && (2 == size) {
+            let mut buf = [0u8; 2];
+            result = self.__read(cmd, value, index, size, &mut buf as *mut _ as _);
+            *(data as *mut u16) = u16::from_le_bytes(buf);
+        } else if (eflag != 0) && (4 == size) {
+            let mut buf = [0u8; 4];
+            result = self.__read(cmd, value, index, size, &mut buf as *mut _ as _);
+            *(data as *mut u32) = u32::from_le_bytes(buf);
+        } else {
+            result = self.__read(cmd, value, index, size, data);
+        }
 
-	if (eflag != 0) && (2 == size) {
-		let mut buf = [0u8; 2];
-		result = __ax88179_read_cmd(dev, cmd, value, index, size, &mut buf as *mut _ as _, 0);
-		*(data as *mut u16) = u16::from_le_bytes(buf);
-	} else if (eflag != 0) && (4 == size) {
-		let mut buf = [0u8; 4];
-		result = __ax88179_read_cmd(dev, cmd, value, index, size, &mut buf as *mut _ as _, 0);
-		*(data as *mut u32) = u32::from_le_bytes(buf);
-	} else {
-		result = __ax88179_read_cmd(dev, cmd, value, index, size, data, 0);
-	}
+        result
+    }
 
-	result
+    /// Writes `data`, whose size gives the length of the transfer.
+    fn write(&self, cmd: u8, value: u16, index: u16, data: impl WriteData) -> DriverResult<()> {
+        let mut data = data.value();
+        let size = data.as_mut().len() as u16;
+        // The constructors vouch for the device, the data is our own
+        unsafe { self.__write(cmd, value, index, size, data.as_mut().as_ptr() as _) }
+    }
 }
 
 trait WriteData {
@@ -632,18 +835,6 @@ impl WriteData for &mut [u8] {
     }
 }
 
-unsafe fn ax88179_write_cmd(
-    dev: *mut usbnet,
-    cmd: u8,
-    value: u16,
-    index: u16,
-    data: impl WriteData,
-) -> KernelResult<()> {
-    let mut data = data.value();
-    let size = data.as_mut().len() as u16;
-    __ax88179_write_cmd(dev, cmd, value, index, size, data.as_mut().as_ptr() as _, 0)
-}
-
 // #if LINUX_VERSION_CODE < KERNEL_VERSION(2, 6, 20)
 // static void ax88179_async_cmd_callback(struct urb *urb, struct pt_regs *regs)
 // #else
@@ -743,227 +934,469 @@ unsafe fn ax88179_write_cmd(
 // 	}
 // }
 
-unsafe extern "C" fn ax88179_status(dev: *mut usbnet, urb: *mut urb) {
-    // 	struct ax88179_int_data *event = NULL;
-    // 	int link = 0;
+fn ax88179_int_status(dev: &UsbNet, event: &[u8]) {
+    if event.len() < AX_INT_DATA_LEN {
+        return;
+    }
 
-    // 	if (urb->actual_length < 8)
-    // 		return;
+    let link = event[AX_INT_DATA_LINK] & AX_INT_PPLS_LINK != 0;
+    let net = dev.net();
 
-    // 	event = urb->transfer_buffer;
-    // 	link = event->link & AX_INT_PPLS_LINK;
+    if net.carrier_ok() != link {
+        if link {
+            dev.defer_kevent(EVENT_LINK_RESET);
+        } else {
+            net.carrier_off();
+            // ax88179_suspend refuses autosuspend while the link is up, and nothing
+            // else would have the USB core try again
+            dev.request_autosuspend();
+        }
 
-    // 	if (netif_carrier_ok(dev->net) != link) {
-    // 		if (link)
-    // 			usbnet_defer_kevent(dev, EVENT_LINK_RESET);
-    // 		else
-    // 			netif_carrier_off(dev->net);
-    // #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 34)
-    // 		netdev_info(dev->net, "ax88179_178a - Link status is: %d\n",
-    // 			    link);
-    // #else
-    // 		devinfo(dev, "ax88179_178a - Link status is: %d\n", link);
-    // #endif
-    // 	}
+        netif_info!(
+            *dev,
+            NETIF_MSG_LINK,
+            "link {}",
+            if link { "up" } else { "down" }
+        );
+    }
 }
 
-// static int ax88179_mdio_read(struct net_device *netdev, int phy_id, int loc)
-// {
-// 	struct usbnet *dev = netdev_priv(netdev);
-// 	u16 res;
-// 	u16 *tmp16;
+unsafe extern "C" fn ax88179_mdio_read(
+    netdev: *mut net_device,
+    phy_id: c_int,
+    loc: c_int,
+) -> c_int {
+    let dev = NetDevice::from_raw(netdev).usbnet();
+
+    match Phy::new(Ax88179::of(dev.as_ptr()))
+        .and_then(|mut phy| phy.mii_read(phy_id as u16, loc as u16))
+    {
+        Ok(res) => res as c_int,
+        Err(e) => e.to_kernel_errno(),
+    }
+}
 
-// 	tmp16 = kmalloc(2, GFP_KERNEL);
-// 	if (!tmp16)
-// 		return -ENOMEM;
+unsafe extern "C" fn ax88179_mdio_write(
+    netdev: *mut net_device,
+    phy_id: c_int,
+    loc: c_int,
+    val: c_int,
+) {
+    let dev = NetDevice::from_raw(netdev).usbnet();
+
+    // The MII library has no way to hear about a failure, so it's only logged
+    if let Err(e) = Phy::new(Ax88179::of(dev.as_ptr()))
+        .and_then(|mut phy| phy.mii_write(phy_id as u16, loc as u16, val as u16))
+    {
+        e.log(&dev, "mdio_write");
+    }
+}
 
-// 	ax88179_read_cmd(dev, AX_ACCESS_PHY, phy_id, (__u16)loc, 2, tmp16, 1);
+// PMSG_IS_AUTO is a macro
+const PM_EVENT_AUTO: c_int = 0x0400;
+
+unsafe fn try_ax88179_suspend(
+    intf: &UsbInterface,
+    dev: &UsbNet,
+    message: pm_message_t,
+) -> DriverResult<()> {
+    let ctx = CmdCtx::nopm(dev);
+    let ax = Ax88179::of(dev.as_ptr());
+    let autosuspend = message.event & PM_EVENT_AUTO != 0;
+
+    // In case power is lost, and reset_resume has to restore everything
+    if let Err(e) = ax.save(&ctx) {
+        e.warn(dev, "saving settings");
+    }
 
-// 	res = *tmp16;
-// 	kfree(tmp16);
+    intf.usbnet_suspend(message)?;
 
-// 	return res;
-// }
+    if let Err(e) = ax88179_enter_suspend(&ctx, ax.reg_monitor, autosuspend) {
+        // Without its wakeup armed the adapter would sleep through the cable being
+        // plugged back in, so refuse to autosuspend. Errors during system sleep are
+        // ignored by the USB core, which suspends the device and calls resume all the
+        // same, so then usbnet has to stay suspended too.
+        if !autosuspend {
+            e.warn(dev, "preparing to suspend");
+            return Ok(());
+        }
 
-// static void ax88179_mdio_write(struct net_device *netdev, int phy_id, int loc,
-// 			       int val)
-// {
-// 	struct usbnet *dev = netdev_priv(netdev);
-// 	u16 *res;
-// 	res = kmalloc(2, GFP_KERNEL);
-// 	if (!res)
-// 		return;
-// 	*res = (u16)val;
+        let _ = intf.usbnet_resume();
+        return Err(e);
+    }
 
-// 	ax88179_write_cmd(dev, AX_ACCESS_PHY, phy_id, (__u16)loc, 2, res);
+    Ok(())
+}
 
-// 	kfree(res);
-// }
+/// Stops the MAC and arms the wakeup events, once usbnet has stopped its transfers.
+unsafe fn ax88179_enter_suspend(
+    ctx: &CmdCtx,
+    mut monitor: u8,
+    autosuspend: bool,
+) -> DriverResult<()> {
+    let mut wolp = [0u8; 38];
+    let mut tmp16: u16 = 0;
+
+    /* Disable RX path */
+    ctx.read(
+        AX_ACCESS_MAC,
+        AX_MEDIUM_STATUS_MODE,
+        2,
+        2,
+        &mut tmp16 as *mut u16 as _,
+        1,
+    )?;
+    tmp16 &= !AX_MEDIUM_RECEIVE_EN;
+    ctx.write(AX_ACCESS_MAC, AX_MEDIUM_STATUS_MODE, 2, tmp16)?;
+
+    /* Force bz */
+    ctx.read(
+        AX_ACCESS_MAC,
+        AX_PHYPWR_RSTCTL,
+        2,
+        2,
+        &mut tmp16 as *mut u16 as _,
+        1,
+    )?;
+    tmp16 |= AX_PHYPWR_RSTCTL_BZ | AX_PHYPWR_RSTCTL_IPRL;
+    ctx.write(AX_ACCESS_MAC, AX_PHYPWR_RSTCTL, 2, tmp16)?;
+
+    wolp[28] = 0x04;
+    wolp[29] = MASK_WAKEUP_EVENT_TIMER;
+    ctx.write(AX_ACCESS_WAKEUP, 0x01, 0, &mut wolp[..])?;
+
+    /* change clock */
+    ctx.write(AX_ACCESS_MAC, AX_CLK_SELECT, 1, 0u8)?;
+
+    /* Configure RX control register => stop operation */
+    ctx.write(AX_ACCESS_MAC, AX_RX_CTL, 2, AX_RX_CTL_STOP)?;
+
+    if autosuspend {
+        monitor |= AX_MONITOR_MODE_RWLC;
+    }
+    ctx.write(AX_ACCESS_MAC, AX_MONITOR_MODE, 1, monitor)
+}
 
 unsafe extern "C" fn ax88179_suspend(intf: *mut usb_interface, message: pm_message_t) -> c_int {
-    // 	struct usbnet *dev = usb_get_intfdata(intf);
-    // 	struct ax88179_data *ax179_data = (struct ax88179_data *)dev->data;
-    // 	u8 wolp[38] = { 0 };
-    // 	u16 tmp16;
-    // 	u8 tmp8;
+    let intf = UsbInterface::from_raw(intf);
+    let dev = match intf.usbnet() {
+        Ok(dev) => dev,
+        Err(e) => return e.to_kernel_errno(),
+    };
 
-    // usbnet_suspend(intf, message);
+    // Only go idle while the cable is unplugged. Plugging it back in wakes the
+    // adapter through the link change wakeup armed by try_ax88179_suspend, unplugging
+    // it has ax88179_int_status ask for another try.
+    if message.event & PM_EVENT_AUTO != 0 && dev.net().carrier_ok() {
+        return Error::EBUSY.to_kernel_errno();
+    }
 
-    // 	/* Disable RX path */
-    // 	ax88179_read_cmd_nopm(dev, AX_ACCESS_MAC, AX_MEDIUM_STATUS_MODE,
-    // 			      2, 2, &tmp16, 1);
-    // 	tmp16 &= ~AX_MEDIUM_RECEIVE_EN;
-    // 	ax88179_write_cmd_nopm(dev, AX_ACCESS_MAC,  AX_MEDIUM_STATUS_MODE,
-    // 			       2, 2, &tmp16);
+    try_ax88179_suspend(&intf, &dev, message).into_errno_logged(&dev, "suspend")
+}
 
-    // 	/* Force bz */
-    // 	ax88179_read_cmd_nopm(dev, AX_ACCESS_MAC, AX_PHYPWR_RSTCTL,
-    // 			      2, 2, &tmp16, 1);
-    // 	tmp16 |= AX_PHYPWR_RSTCTL_BZ | AX_PHYPWR_RSTCTL_IPRL;
-    // 	ax88179_write_cmd_nopm(dev, AX_ACCESS_MAC, AX_PHYPWR_RSTCTL,
-    // 			       2, 2, &tmp16);
+// MMD devices and registers, from linux/mdio.h
+const MDIO_MMD_PCS: u16 = 3;
+const MDIO_MMD_AN: u16 = 7;
+const MDIO_PCS_EEE_ABLE: u16 = 20; /* EEE Capability register */
+const MDIO_AN_EEE_ADV: u16 = 60; /* EEE advertisement */
+const MDIO_AN_EEE_LPABLE: u16 = 61; /* EEE link partner ability */
+const MDIO_EEE_100TX: u16 = 0x0002;
+const MDIO_EEE_1000T: u16 = 0x0004;
 
-    // 	wolp[28] = 0x04;
-    // 	wolp[29] = MASK_WAKEUP_EVENT_TIMER;
-    // 	ax88179_write_cmd_nopm(dev, AX_ACCESS_WAKEUP, 0x01, 0, 38, wolp);
+/* The GigaPHY supports EEE at 100 and 1000 Mbps */
+const AX88179_EEE_ADV: u16 = MDIO_EEE_100TX | MDIO_EEE_1000T;
 
-    // 	/* change clock */
-    // 	tmp8 = 0;
-    // 	ax88179_write_cmd_nopm(dev, AX_ACCESS_MAC, AX_CLK_SELECT, 1, 1, &tmp8);
+fn ax88179_eee_setting(phy: &mut Phy, advertise: u16) -> DriverResult<()> {
+    phy.mmd_write(MDIO_MMD_AN, MDIO_AN_EEE_ADV, advertise)
+}
 
-    // 	/* Configure RX control register => stop operation */
-    // 	tmp16 = AX_RX_CTL_STOP;
-    // 	ax88179_write_cmd_nopm(dev, AX_ACCESS_MAC, AX_RX_CTL, 2, 2, &tmp16);
+fn ax88179_gether_setting(phy: &mut Phy, enable: bool) -> DriverResult<()> {
+    if enable {
+        // Enable Green Ethernet
+        phy.page::<Page3>()?.write(PAGE3_GETH_CTRL, 0x3247)?;
+        phy.page::<Page5>()?.write(PAGE5_GETH_TUNE, 0x0680)?;
+    } else {
+        // Disable Green Ethernet
+        phy.page::<Page3>()?.write(PAGE3_GETH_CTRL, 0x3246)?;
+    }
 
-    // 	tmp8 = ax179_data->reg_monitor;
-    // 	ax88179_write_cmd_nopm(dev, AX_ACCESS_MAC, AX_MONITOR_MODE, 1, 1, &tmp8);
+    Ok(())
+}
 
-    return 0;
+// NET_IP_ALIGN from skbuff.h, x86 overrides it to 0
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const NET_IP_ALIGN: u32 = 0;
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+const NET_IP_ALIGN: u32 = 2;
+
+/// RX control while running: accept our own, broadcast and all multicast frames.
+const AX_RX_CTL_DEFAULT: u16 = AX_RX_CTL_DROPCRCERR
+    | AX_RX_CTL_START
+    | AX_RX_CTL_AP
+    | AX_RX_CTL_AMALL
+    | AX_RX_CTL_AB
+    | if NET_IP_ALIGN == 0 { AX_RX_CTL_IPE } else { 0 };
+
+unsafe fn try_ax88179_resume(intf: &UsbInterface, dev: &UsbNet) -> DriverResult<()> {
+    let wake = ax88179_wake(&CmdCtx::nopm(dev));
+
+    // usbnet restarts its transfers whatever state the registers are in, else the
+    // interface would stay dead until replugged
+    intf.usbnet_resume()?;
+    wake
 }
 
-// static void ax88179_EEE_setting(struct usbnet *dev)
-// {
-// 	u16 tmp16;
+/// Undoes ax88179_enter_suspend, before usbnet restarts its transfers.
+unsafe fn ax88179_wake(ctx: &CmdCtx) -> DriverResult<()> {
+    let mut tmp8: u8 = 0;
 
-// 	if (bEEE) {
-// 		// Enable EEE
-// 		tmp16 = 0x07;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  GMII_PHY_MACR, 2, &tmp16);
+    UsbNet::from_raw(ctx.dev).net().carrier_off();
 
-// 		tmp16 = 0x3c;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  GMII_PHY_MAADR, 2, &tmp16);
+    /* Power up ethernet PHY */
+    ctx.write(AX_ACCESS_MAC, AX_PHYPWR_RSTCTL, 2, 0u16)?;
+    msleep(1);
+    ctx.write(AX_ACCESS_MAC, AX_PHYPWR_RSTCTL, 2, AX_PHYPWR_RSTCTL_IPRL)?;
+    msleep(200);
 
-// 		tmp16 = 0x4007;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  GMII_PHY_MACR, 2, &tmp16);
+    /* Ethernet PHY Auto Detach*/
+    ax88179_AutoDetach(ctx)?;
 
-// 		tmp16 = 0x06;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  GMII_PHY_MAADR, 2, &tmp16);
-// 	} else {
-// 		// Disable EEE
-// 		tmp16 = 0x07;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  GMII_PHY_MACR, 2, &tmp16);
-
-// 		tmp16 = 0x3c;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  GMII_PHY_MAADR, 2, &tmp16);
-
-// 		tmp16 = 0x4007;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  GMII_PHY_MACR, 2, &tmp16);
-
-// 		tmp16 = 0x00;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  GMII_PHY_MAADR, 2, &tmp16);
-// 	}
-// }
+    /* change clock */
+    ctx.read(
+        AX_ACCESS_MAC,
+        AX_CLK_SELECT,
+        1,
+        1,
+        &mut tmp8 as *mut u8 as _,
+        0,
+    )?;
+    tmp8 |= AX_CLK_SELECT_ACS | AX_CLK_SELECT_BCS;
+    ctx.write(AX_ACCESS_MAC, AX_CLK_SELECT, 1, tmp8)?;
+    msleep(100);
 
-// static void ax88179_Gether_setting(struct usbnet *dev)
-// {
-// 	u16 tmp16;
+    /* Configure RX control register => start operation */
+    ctx.write(AX_ACCESS_MAC, AX_RX_CTL, 2, Ax88179::of(ctx.dev).rxctl)
+}
+
+unsafe extern "C" fn ax88179_resume(intf: *mut usb_interface) -> c_int {
+    let intf = UsbInterface::from_raw(intf);
 
-// 	if (bGETH) {
-// 		// Enable Green Ethernet
-// 		tmp16 = 0x03;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  31, 2, &tmp16);
+    match intf.usbnet() {
+        Ok(dev) => try_ax88179_resume(&intf, &dev).into_errno_logged(&dev, "resume"),
+        Err(e) => e.to_kernel_errno(),
+    }
+}
 
-// 		tmp16 = 0x3247;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  25, 2, &tmp16);
+// MII registers bits, from linux/mii.h
+const BMCR_ANRESTART: u16 = 0x0200; /* Auto negotiation restart */
+const BMCR_ISOLATE: u16 = 0x0400; /* Isolate data paths from MII */
+const BMCR_PDOWN: u16 = 0x0800; /* Enable low power state */
+const BMCR_ANENABLE: u16 = 0x1000; /* Enable auto negotiation */
+const BMCR_RESET: u16 = 0x8000; /* Reset to default state */
+
+impl Ax88179 {
+    /// Writes the MAC side configuration: checksum offload, RX control, the multicast
+    /// filter, wakeup and the default medium mode for the current MTU.
+    unsafe fn write_config(&self, ctx: &CmdCtx) -> DriverResult<()> {
+        /* Enable checksum offload */
+        let rxcoe = if self.checksum & AX_RX_CHECKSUM != 0 {
+            AX_RXCOE_IP | AX_RXCOE_TCP | AX_RXCOE_UDP | AX_RXCOE_TCPV6 | AX_RXCOE_UDPV6
+        } else {
+            0
+        };
+        ctx.write(AX_ACCESS_MAC, AX_RXCOE_CTL, 1, rxcoe)?;
 
-// 		tmp16 = 0x05;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  31, 2, &tmp16);
+        let txcoe = if self.checksum & AX_TX_CHECKSUM != 0 {
+            AX_TXCOE_IP | AX_TXCOE_TCP | AX_TXCOE_UDP | AX_TXCOE_TCPV6 | AX_TXCOE_UDPV6
+        } else {
+            0
+        };
+        ctx.write(AX_ACCESS_MAC, AX_TXCOE_CTL, 1, txcoe)?;
+
+        /* Configure RX control register => start operation */
+        ctx.write(AX_ACCESS_MAC, AX_RX_CTL, 2, self.rxctl)?;
+
+        let mut filter = self.multi_filter;
+        ctx.write(
+            AX_ACCESS_MAC,
+            AX_MULTI_FILTER_ARRY,
+            AX_MCAST_FILTER_SIZE as u16,
+            &mut filter[..],
+        )?;
+
+        ctx.write(AX_ACCESS_MAC, AX_MONITOR_MODE, 1, self.reg_monitor)?;
+
+        /* Configure default medium type => giga */
+        let mut medium = AX_MEDIUM_TXFLOW_CTRLEN
+            | AX_MEDIUM_RXFLOW_CTRLEN
+            | AX_MEDIUM_FULL_DUPLEX
+            | AX_MEDIUM_GIGAMODE;
+        if UsbNet::from_raw(self.dev).net().mtu() > 1500 {
+            medium |= AX_MEDIUM_JUMBO_EN;
+        }
+        ctx.write(AX_ACCESS_MAC, AX_MEDIUM_STATUS_MODE, 2, medium)
+    }
 
-// 		tmp16 = 0x0680;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  1, 2, &tmp16);
+    /// Takes a snapshot of the link settings ethtool writes straight into the PHY,
+    /// the only ones not tracked here or in the net_device as they change.
+    unsafe fn save(&self, ctx: &CmdCtx) -> DriverResult<()> {
+        let (bmcr, advertise, ctrl1000) = {
+            let mut phy = Phy::with_ctx(self, *ctx)?;
+            (
+                phy.read(PHY_BMCR)?,
+                phy.read(PHY_ADVERTISE)?,
+                phy.read(PHY_CTRL1000)?,
+            )
+        };
+
+        self.bmcr.store(bmcr, Ordering::Relaxed);
+        self.advertise.store(advertise, Ordering::Relaxed);
+        self.ctrl1000.store(ctrl1000, Ordering::Relaxed);
+
+        Ok(())
+    }
 
-// 		tmp16 = 0;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  31, 2, &tmp16);
-// 	} else {
-// 		// Disable Green Ethernet
-// 		tmp16 = 0x03;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  31, 2, &tmp16);
-
-// 		tmp16 = 0x3246;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  25, 2, &tmp16);
-
-// 		tmp16 = 0;
-// 		ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 				  31, 2, &tmp16);
-// 	}
-// }
+    /// Reprograms a chip which lost its registers, to a USB reset or to losing power
+    /// while suspended, from the last snapshot. The PHY must already be powered up.
+    unsafe fn restore(&self, ctx: &CmdCtx) -> DriverResult<()> {
+        let dev = ctx.dev;
+        let mut phy = Phy::with_ctx(self, *ctx)?;
+
+        /* Set the MAC address */
+        let mut addr = UsbNet::from_raw(dev).net().dev_addr();
+        ctx.write(AX_ACCESS_MAC, AX_NODE_ID, ETH_ALEN as u16, &mut addr[..])?;
+
+        /* RX bulk configuration */
+        let bulkin = AX88179_BULKIN_SIZE[0].with_overrides(
+            self.bsize.load(Ordering::Relaxed),
+            self.ifg.load(Ordering::Relaxed),
+        );
+        ctx.write(
+            AX_ACCESS_MAC,
+            AX_RX_BULKIN_QCTRL,
+            5,
+            &mut bulkin.to_bytes()[..],
+        )?;
+        (*dev).rx_urb_size = bulkin.rx_urb_size() as _;
+
+        ctx.write(AX_ACCESS_MAC, AX_PAUSE_WATERLVL_LOW, 1, 0x34u8)?;
+        ctx.write(AX_ACCESS_MAC, AX_PAUSE_WATERLVL_HIGH, 1, 0x52u8)?;
+
+        self.write_config(ctx)?;
+
+        // The LEDs are cosmetic, don't fail over them
+        if let Err(e) = ax88179_led_setting(&mut phy, self.led_mode) {
+            e.warn(&ctx.usbnet(), "configuring LEDs");
+        }
 
-unsafe extern "C" fn ax88179_resume(intf: *mut usb_interface) -> c_int {
-    return 0;
+        ax88179_eee_setting(&mut phy, self.eee_advertisement())?;
+        ax88179_gether_setting(&mut phy, self.geth.load(Ordering::Relaxed))?;
 
-    // 	struct usbnet *dev = usb_get_intfdata(intf);
-    // 	u16 tmp16;
-    // 	u8 tmp8;
+        /* Link settings, then renegotiate with them */
+        phy.write(PHY_ADVERTISE, self.advertise.load(Ordering::Relaxed))?;
+        phy.write(PHY_CTRL1000, self.ctrl1000.load(Ordering::Relaxed))?;
+        let mut bmcr =
+            self.bmcr.load(Ordering::Relaxed) & !(BMCR_RESET | BMCR_PDOWN | BMCR_ISOLATE);
+        if bmcr & BMCR_ANENABLE != 0 {
+            bmcr |= BMCR_ANRESTART;
+        }
+        phy.write(PHY_BMCR, bmcr)
+    }
+}
 
-    // 	netif_carrier_off(dev->net);
+/// Brings a chip back from its default registers, see Ax88179::restore.
+unsafe fn ax88179_reinit(ctx: &CmdCtx) -> DriverResult<()> {
+    UsbNet::from_raw(ctx.dev).net().carrier_off();
 
-    // 	/* Power up ethernet PHY */
-    // 	tmp16 = 0;
-    // 	ax88179_write_cmd_nopm(dev, AX_ACCESS_MAC, AX_PHYPWR_RSTCTL,
-    // 			       2, 2, &tmp16);
-    // #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 36)
-    // 	usleep_range(1000, 2000);
-    // #else
-    // 	msleep(1);
-    // #endif
-    // 	tmp16 = AX_PHYPWR_RSTCTL_IPRL;
-    // 	ax88179_write_cmd_nopm(dev, AX_ACCESS_MAC, AX_PHYPWR_RSTCTL,
-    // 			       2, 2, &tmp16);
-    // 	msleep(200);
+    /* Power up ethernet PHY */
+    ctx.write(AX_ACCESS_MAC, AX_PHYPWR_RSTCTL, 2, 0u16)?;
+    ctx.write(AX_ACCESS_MAC, AX_PHYPWR_RSTCTL, 2, AX_PHYPWR_RSTCTL_IPRL)?;
+    msleep(200);
 
-    // 	/* Ethernet PHY Auto Detach*/
-    // 	ax88179_AutoDetach(dev, 1);
+    ctx.write(
+        AX_ACCESS_MAC,
+        AX_CLK_SELECT,
+        1,
+        AX_CLK_SELECT_ACS | AX_CLK_SELECT_BCS,
+    )?;
+    msleep(100);
 
-    // 	/* change clock */
-    // 	ax88179_read_cmd_nopm(dev, AX_ACCESS_MAC,  AX_CLK_SELECT,
-    // 			      1, 1, &tmp8, 0);
-    // 	tmp8 |= AX_CLK_SELECT_ACS | AX_CLK_SELECT_BCS;
-    // 	ax88179_write_cmd_nopm(dev, AX_ACCESS_MAC, AX_CLK_SELECT, 1, 1, &tmp8);
-    // 	msleep(100);
+    /* Ethernet PHY Auto Detach*/
+    ax88179_AutoDetach(ctx)?;
+
+    Ax88179::of(ctx.dev).restore(ctx)
+}
+
+/// The adapter lost power or was reset while suspended, and is back on its default
+/// registers.
+unsafe fn try_ax88179_reset_resume(intf: &UsbInterface, dev: &UsbNet) -> DriverResult<()> {
+    let reinit = ax88179_reinit(&CmdCtx::nopm(dev));
+
+    // As for resume, usbnet restarts whether or not the registers could be restored
+    intf.usbnet_resume()?;
+    reinit
+}
+
+unsafe extern "C" fn ax88179_reset_resume(intf: *mut usb_interface) -> c_int {
+    let intf = UsbInterface::from_raw(intf);
+
+    match intf.usbnet() {
+        Ok(dev) => try_ax88179_reset_resume(&intf, &dev).into_errno_logged(&dev, "reset_resume"),
+        Err(e) => e.to_kernel_errno(),
+    }
+}
+
+// PMSG_SUSPEND is a macro
+const PM_EVENT_SUSPEND: c_int = 0x0002;
+
+unsafe fn try_ax88179_pre_reset(intf: &UsbInterface, dev: &UsbNet) -> DriverResult<()> {
+    let ctx = CmdCtx::new(dev.as_ptr())?;
+
+    if let Err(e) = Ax88179::of(dev.as_ptr()).save(&ctx) {
+        e.warn(dev, "saving settings");
+    }
+
+    intf.usbnet_suspend(pm_message_t {
+        event: PM_EVENT_SUSPEND,
+    })?;
+    Ok(())
+}
+
+/// Quiesces usbnet before the USB core resets the port, see ax88179_post_reset.
+unsafe extern "C" fn ax88179_pre_reset(intf: *mut usb_interface) -> c_int {
+    let intf = UsbInterface::from_raw(intf);
+
+    match intf.usbnet() {
+        Ok(dev) => try_ax88179_pre_reset(&intf, &dev).into_errno_logged(&dev, "pre_reset"),
+        Err(e) => e.to_kernel_errno(),
+    }
+}
 
-    // 	/* Configure RX control register => start operation */
-    // 	tmp16 = AX_RX_CTL_DROPCRCERR | AX_RX_CTL_START | AX_RX_CTL_AP |
-    // 		 AX_RX_CTL_AMALL | AX_RX_CTL_AB;
-    // 	if (NET_IP_ALIGN == 0)
-    // 		tmp16 |= AX_RX_CTL_IPE;
-    // 	ax88179_write_cmd_nopm(dev, AX_ACCESS_MAC, AX_RX_CTL, 2, 2, &tmp16);
+unsafe fn try_ax88179_post_reset(intf: &UsbInterface, dev: &UsbNet) -> DriverResult<()> {
+    let reinit = ax88179_reinit(&CmdCtx::new(dev.as_ptr())?);
 
-    // 	return usbnet_resume(intf);
+    // Undoes pre_reset's usbnet_suspend whatever happened to the registers
+    intf.usbnet_resume()?;
+    reinit
+}
+
+/// The port was reset, which cleared every register.
+unsafe extern "C" fn ax88179_post_reset(intf: *mut usb_interface) -> c_int {
+    let intf = UsbInterface::from_raw(intf);
+
+    match intf.usbnet() {
+        Ok(dev) => try_ax88179_post_reset(&intf, &dev).into_errno_logged(&dev, "post_reset"),
+        Err(e) => e.to_kernel_errno(),
+    }
+}
+
+/// usbnet calls this with `on` set while the interface is up, so that it may
+/// autosuspend. It's woken again by the link change wakeup.
+unsafe extern "C" fn ax88179_manage_power(dev: *mut usbnet, on: c_int) -> c_int {
+    UsbNet::from_raw(dev)
+        .intf()
+        .set_needs_remote_wakeup(on != 0);
+    0
 }
 
 // static void
@@ -1436,518 +1869,474 @@ unsafe extern "C" fn ax88179_resume(intf: *mut usb_interface) -> c_int {
 // };
 // #endif
 
-// static int ax88179_check_eeprom(struct usbnet *dev)
-// {
-// 	u8 i = 0;
-// 	u8 *buf;
-// 	u8 *eeprom;
-// 	u16 csum = 0, delay = HZ / 10;
-// 	unsigned long jtimeout = 0;
-
-// 	eeprom = kmalloc(22, GFP_KERNEL);
-// 	if (!eeprom)
-// 		return -ENOMEM;
-// 	buf = &eeprom[20];
-
-// 	/* Read EEPROM content */
-// 	for (i = 0 ; i < 6; i++) {
-// 		buf[0] = i;
-// 		if (ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_SROM_ADDR,
-// 				      1, 1, buf) < 0) {
-// 			kfree(eeprom);
-// 			return -EINVAL;
-// 		}
+/// Waits for the EEPROM access module to finish the last AX_SROM_CMD.
+unsafe fn ax88179_srom_wait(dev: *mut usbnet) -> DriverResult<()> {
+    let ctx = CmdCtx::new(dev)?;
+
+    let mut cmd: u8 = 0;
+
+    let timeout = Timeout::from_msecs(100);
+    loop {
+        ctx.read(
+            AX_ACCESS_MAC,
+            AX_SROM_CMD,
+            1,
+            1,
+            &mut cmd as *mut u8 as _,
+            0,
+        )?;
+
+        if cmd & EEP_BUSY == 0 {
+            return Ok(());
+        }
+        if timeout.expired() {
+            return Err(Error::EINVAL.into());
+        }
+    }
+}
 
-// 		buf[0] = EEP_RD;
-// 		if (ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_SROM_CMD,
-// 				      1, 1, buf) < 0) {
-// 			kfree(eeprom);
-// 			return -EINVAL;
-// 		}
+/// Reads one 16 bit EEPROM word through the SROM registers.
+unsafe fn ax88179_srom_read(dev: *mut usbnet, offset: u8) -> DriverResult<[u8; 2]> {
+    let ctx = CmdCtx::new(dev)?;
 
-// 		jtimeout = jiffies + delay;
-// 		do {
-// 			ax88179_read_cmd(dev, AX_ACCESS_MAC, AX_SROM_CMD,
-// 					 1, 1, buf, 0);
+    let mut word = [0u8; 2];
 
-// 			if (time_after(jiffies, jtimeout)) {
-// 				kfree(eeprom);
-// 				return -EINVAL;
-// 			}
-// 		} while (buf[0] & EEP_BUSY);
+    ctx.write(AX_ACCESS_MAC, AX_SROM_ADDR, 1, offset)?;
+    ctx.write(AX_ACCESS_MAC, AX_SROM_CMD, 1, EEP_RD)?;
+    ax88179_srom_wait(dev)?;
+    ctx.read(
+        AX_ACCESS_MAC,
+        AX_SROM_DATA_LOW,
+        2,
+        2,
+        word.as_mut_ptr() as _,
+        0,
+    )?;
+
+    Ok(word)
+}
 
-// 		ax88179_read_cmd(dev, AX_ACCESS_MAC, AX_SROM_DATA_LOW,
-// 				 2, 2, &eeprom[i * 2], 0);
+/// Reads the EEPROM header covered by its checksum. Stops after the first word if
+/// that shows the EEPROM to be blank.
+unsafe fn ax88179_read_eeprom_header(
+    dev: *mut usbnet,
+) -> DriverResult<[u8; nvm::EEPROM_HEADER_LEN]> {
+    let mut eeprom = [0u8; nvm::EEPROM_HEADER_LEN];
 
-// 		if ((i == 0) && (eeprom[0] == 0xFF)) {
-// 			kfree(eeprom);
-// 			return -EINVAL;
-// 		}
-// 	}
+    for i in 0..nvm::EEPROM_HEADER_LEN / 2 {
+        let word = ax88179_srom_read(dev, i as u8)?;
+        eeprom[i * 2..i * 2 + 2].copy_from_slice(&word);
 
-// 	csum = eeprom[6] + eeprom[7] + eeprom[8] + eeprom[9];
-// 	csum = (csum >> 8) + (csum & 0xff);
+        if i == 0 && eeprom[0] == 0xff {
+            break;
+        }
+    }
 
-// 	if ((csum + eeprom[10]) == 0xff) {
-// 		kfree(eeprom);
-// 		return AX_EEP_EFUSE_CORRECT;
-// 	} else {
-// 		kfree(eeprom);
-// 		return -EINVAL;
-// 	}
-// }
+    Ok(eeprom)
+}
 
-// static int ax88179_check_efuse(struct usbnet *dev, void *ledmode)
-// {
-// 	u8	i = 0;
-// 	u16	csum = 0;
-// 	u8	*efuse;
+unsafe fn ax88179_read_efuse(dev: *mut usbnet) -> DriverResult<[u8; nvm::EFUSE_LEN]> {
+    let ctx = CmdCtx::new(dev)?;
 
-// 	efuse = kmalloc(64, GFP_KERNEL);
-// 	if (!efuse)
-// 		return -ENOMEM;
+    let mut efuse = [0u8; nvm::EFUSE_LEN];
 
-// 	if (ax88179_read_cmd(dev, AX_ACCESS_EFUSE, 0, 64, 64, efuse, 0) < 0) {
-// 		kfree(efuse);
-// 		return -EINVAL;
-// 	}
+    ctx.read(AX_ACCESS_EFUSE, 0, 64, 64, efuse.as_mut_ptr() as _, 0)?;
 
-// 	if (efuse[0] == 0xFF) {
-// 		kfree(efuse);
-// 		return -EINVAL;
-// 	}
+    Ok(efuse)
+}
 
-// 	for (i = 0; i < 64; i++)
-// 		csum = csum + efuse[i];
+/// Burns `new` into a blank eFuse, as planned by nvm::plan_efuse_write from the
+/// `current` contents. Only the 16 bit words which change are written, then the
+/// eFuse is read back to check every bit took.
+unsafe fn ax88179_write_efuse(
+    dev: *mut usbnet,
+    current: &[u8; nvm::EFUSE_LEN],
+    new: &[u8; nvm::EFUSE_LEN],
+) -> DriverResult<()> {
+    let ctx = CmdCtx::new(dev)?;
 
-// 	while (csum > 255)
-// 		csum = (csum & 0x00FF) + ((csum >> 8) & 0x00FF);
+    ctx.write(AX_WRITE_EFUSE_EN, 0, 0, ())?;
 
-// 	if (csum == 0xFF) {
-// 		memcpy((u8 *)ledmode, &efuse[51], 2);
-// 		kfree(efuse);
-// 		return AX_EEP_EFUSE_CORRECT;
-// 	} else {
-// 		kfree(efuse);
-// 		return -EINVAL;
-// 	}
-// }
+    let mut result = Ok(());
+    for (i, (cur, new)) in current.chunks_exact(2).zip(new.chunks_exact(2)).enumerate() {
+        if cur == new {
+            continue;
+        }
 
-// static int ax88179_convert_old_led(struct usbnet *dev, u8 efuse, void *ledvalue)
-// {
-// 	u8 ledmode = 0;
-// 	u16 *tmp16;
-// 	u16 led = 0;
+        let word = u16::from_le_bytes([new[0], new[1]]);
+        result = ctx.write(AX_ACCESS_EFUSE, i as u16, 1, word);
+        if result.is_err() {
+            break;
+        }
+        msleep(20);
+    }
 
-// 	tmp16 = kmalloc(2, GFP_KERNEL);
-// 	if (!tmp16)
-// 		return -ENOMEM;
+    // Always leave programming mode, whatever happened above
+    let disable = ctx.write(AX_WRITE_EFUSE_DIS, 0, 0, ());
+    result?;
+    disable?;
 
-// 	/* loaded the old eFuse LED Mode */
-// 	if (efuse) {
-// 		if (ax88179_read_cmd(dev, AX_ACCESS_EFUSE, 0x18,
-// 				     1, 2, tmp16, 1) < 0) {
-// 			kfree(tmp16);
-// 			return -EINVAL;
-// 	}
-// 		ledmode = (u8)(*tmp16 & 0xFF);
-// 	} else { /* loaded the old EEprom LED Mode */
-// 		if (ax88179_read_cmd(dev, AX_ACCESS_EEPROM, 0x3C,
-// 				     1, 2, tmp16, 1) < 0) {
-// 			kfree(tmp16);
-// 			return -EINVAL;
-// 		}
-// 		ledmode = (u8) (*tmp16 >> 8);
-// 	}
-// #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 34)
-// 	netdev_dbg(dev->net, "Old LED Mode = %02X\n", ledmode);
-// #else
-// 	devdbg(dev, "Old LED Mode = %02X\n", ledmode);
-// #endif
-// 	switch (ledmode) {
-// 	case 0xFF:
-// 		led = LED0_ACTIVE | LED1_LINK_10 | LED1_LINK_100 |
-// 		      LED1_LINK_1000 | LED2_ACTIVE | LED2_LINK_10 |
-// 		      LED2_LINK_100 | LED2_LINK_1000 | LED_VALID;
-// 		break;
-// 	case 0xFE:
-// 		led = LED0_ACTIVE | LED1_LINK_1000 | LED2_LINK_100 | LED_VALID;
-// 		break;
-// 	case 0xFD:
-// 		led = LED0_ACTIVE | LED1_LINK_1000 | LED2_LINK_100 |
-// 		      LED2_LINK_10 | LED_VALID;
-// 		break;
-// 	case 0xFC:
-// 		led = LED0_ACTIVE | LED1_ACTIVE | LED1_LINK_1000 | LED2_ACTIVE |
-// 		      LED2_LINK_100 | LED2_LINK_10 | LED_VALID;
-// 		break;
-// 	default:
-// 		led = LED0_ACTIVE | LED1_LINK_10 | LED1_LINK_100 |
-// 		      LED1_LINK_1000 | LED2_ACTIVE | LED2_LINK_10 |
-// 		      LED2_LINK_100 | LED2_LINK_1000 | LED_VALID;
-// 		break;
-// 	}
+    /* reload efuse data */
+    ctx.write(AX_RELOAD_EEPROM_EFUSE, 0, 0, ())?;
 
-// 	memcpy((u8 *)ledvalue, &led, 2);
-// 	kfree(tmp16);
+    if ax88179_read_efuse(dev)? != *new {
+        return Err(Error::EIO.into());
+    }
 
-// 	return 0;
-// }
+    Ok(())
+}
 
-// static int ax88179_led_setting(struct usbnet *dev)
-// {
+/// Finds out whether the configuration in the EEPROM, or failing that the eFuse, can
+/// be trusted. Read failures count as invalid.
+unsafe fn ax88179_check_nvm(dev: *mut usbnet) -> nvm::Nvm {
+    match ax88179_read_eeprom_header(dev) {
+        Ok(header) if nvm::eeprom_valid(&header) => return nvm::Nvm::Eeprom,
+        _ => {}
+    }
 
-// 	u16 ledvalue = 0, delay = HZ / 10;
-// 	u16 *ledact, *ledlink;
-// 	u16 *tmp16;
-// 	u8 *value;
-// 	u8 *tmp;
-// 	unsigned long jtimeout = 0;
+    match ax88179_read_efuse(dev).ok().and_then(nvm::Efuse::new) {
+        Some(efuse) => nvm::Nvm::Efuse(efuse),
+        None => nvm::Nvm::Neither,
+    }
+}
 
-// 	tmp = kmalloc(6, GFP_KERNEL);
-// 	if (!tmp)
-// 		return -ENOMEM;
+/// Reads the legacy one byte LED preset from the EEPROM and converts it to an LED
+/// mode. The C driver also has an eFuse variant, but never calls it.
+unsafe fn ax88179_convert_old_led(dev: *mut usbnet) -> DriverResult<u16> {
+    let ctx = CmdCtx::new(dev)?;
 
-// 	value = (u8*)tmp;
-// 	tmp16 = (u16*)tmp;
-// 	ledact = (u16*)(&tmp[2]);
-// 	ledlink = (u16*)(&tmp[4]);
-
-// 	/* Check AX88179 version. UA1 or UA2 */
-// 	ax88179_read_cmd(dev, AX_ACCESS_MAC, GENERAL_STATUS, 1, 1, value, 0);
-
-// 	/* UA1 */
-// 	if (!(*value & AX_SECLD)) {
-// 		*value = AX_GPIO_CTRL_GPIO3EN | AX_GPIO_CTRL_GPIO2EN |
-// 			AX_GPIO_CTRL_GPIO1EN;
-// 		if (ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_GPIO_CTRL,
-// 				      1, 1, value) < 0) {
-// 			kfree(tmp);
-// 			return -EINVAL;
-// 		}
-// 	}
+    let mut tmp16: u16 = 0;
 
-// 	/* check EEprom */
-// 	if (ax88179_check_eeprom(dev) == AX_EEP_EFUSE_CORRECT) {
-// 		*value = 0x42;
-// 		if (ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_SROM_ADDR,
-// 				      1, 1, value) < 0) {
-// 			kfree(tmp);
-// 			return -EINVAL;
-// 		}
+    /* loaded the old EEprom LED Mode */
+    ctx.read(AX_ACCESS_EEPROM, 0x3c, 1, 2, &mut tmp16 as *mut u16 as _, 1)?;
+    let ledmode = (tmp16 >> 8) as u8;
 
-// 		*value = EEP_RD;
-// 		if (ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_SROM_CMD,
-// 				      1, 1, value) < 0) {
-// 			kfree(tmp);
-// 			return -EINVAL;
-// 		}
+    netif_dbg!(
+        UsbNet::from_raw(dev),
+        NETIF_MSG_HW,
+        "old LED mode {ledmode:#04x}"
+    );
 
-// 		jtimeout = jiffies + delay;
-// 		do {
-// 			ax88179_read_cmd(dev, AX_ACCESS_MAC, AX_SROM_CMD,
-// 					 1, 1, value, 0);
-
-// 			ax88179_read_cmd(dev, AX_ACCESS_MAC, AX_SROM_CMD,
-// 					 1, 1, value, 0);
-
-// 			if (time_after(jiffies, jtimeout))
-// 				return -EINVAL;
-// 		} while (*value & EEP_BUSY);
-
-// 		ax88179_read_cmd(dev, AX_ACCESS_MAC, AX_SROM_DATA_HIGH,
-// 				 1, 1, value, 0);
-// 		ledvalue = (*value << 8);
-// 		ax88179_read_cmd(dev, AX_ACCESS_MAC, AX_SROM_DATA_LOW,
-// 				 1, 1, value, 0);
-// 		ledvalue |= *value;
-
-// 		/* load internal ROM for defaule setting */
-// 		if ((ledvalue == 0xFFFF) || ((ledvalue & LED_VALID) == 0))
-// 			ax88179_convert_old_led(dev, 0, &ledvalue);
-
-// 	} else if (ax88179_check_efuse(dev, &ledvalue) ==
-// 				       AX_EEP_EFUSE_CORRECT) { /* check efuse */
-// 		if ((ledvalue == 0xFFFF) || ((ledvalue & LED_VALID) == 0))
-// 			ax88179_convert_old_led(dev, 0, &ledvalue);
-// 	} else {
-// 		ax88179_convert_old_led(dev, 0, &ledvalue);
-// 	}
+    Ok(led::convert_old_led(ledmode))
+}
 
-// 	*tmp16 = GMII_PHY_PAGE_SELECT_EXT;
-// 	ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 			  GMII_PHY_PAGE_SELECT, 2, tmp16);
-
-// 	*tmp16 = 0x2c;
-// 	ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 			  GMII_PHYPAGE, 2, tmp16);
-
-// 	ax88179_read_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 			 GMII_LED_ACTIVE, 2, ledact, 1);
-
-// 	ax88179_read_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 			 GMII_LED_LINK, 2, ledlink, 1);
-
-// 	*ledact &= GMII_LED_ACTIVE_MASK;
-// 	*ledlink &= GMII_LED_LINK_MASK;
-
-// 	if (ledvalue & LED0_ACTIVE)
-// 		*ledact |= GMII_LED0_ACTIVE;
-// 	if (ledvalue & LED1_ACTIVE)
-// 		*ledact |= GMII_LED1_ACTIVE;
-// 	if (ledvalue & LED2_ACTIVE)
-// 		*ledact |= GMII_LED2_ACTIVE;
-
-// 	if (ledvalue & LED0_LINK_10)
-// 		*ledlink |= GMII_LED0_LINK_10;
-// 	if (ledvalue & LED1_LINK_10)
-// 		*ledlink |= GMII_LED1_LINK_10;
-// 	if (ledvalue & LED2_LINK_10)
-// 		*ledlink |= GMII_LED2_LINK_10;
-
-// 	if (ledvalue & LED0_LINK_100)
-// 		*ledlink |= GMII_LED0_LINK_100;
-// 	if (ledvalue & LED1_LINK_100)
-// 		*ledlink |= GMII_LED1_LINK_100;
-// 	if (ledvalue & LED2_LINK_100)
-// 		*ledlink |= GMII_LED2_LINK_100;
-
-// 	if (ledvalue & LED0_LINK_1000)
-// 		*ledlink |= GMII_LED0_LINK_1000;
-// 	if (ledvalue & LED1_LINK_1000)
-// 		*ledlink |= GMII_LED1_LINK_1000;
-// 	if (ledvalue & LED2_LINK_1000)
-// 		*ledlink |= GMII_LED2_LINK_1000;
-
-// 	ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 			  GMII_LED_ACTIVE, 2, ledact);
-
-// 	ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 			  GMII_LED_LINK, 2, ledlink);
-
-// 	*tmp16 = GMII_PHY_PAGE_SELECT_PAGE0;
-// 	ax88179_write_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID,
-// 			  GMII_PHY_PAGE_SELECT, 2, tmp16);
-
-// 	/* LED full duplex setting */
-// 	*tmp16 = 0;
-// 	if (ledvalue & LED0_FD)
-// 		*tmp16 |= 0x01;
-// 	else if ((ledvalue & LED0_USB3_MASK) == 0)
-// 		*tmp16 |= 0x02;
-
-// 	if (ledvalue & LED1_FD)
-// 		*tmp16 |= 0x04;
-// 	else if ((ledvalue & LED1_USB3_MASK) == 0)
-// 		*tmp16 |= 0x08;
-
-// 	if (ledvalue & LED2_FD) /* LED2_FD */
-// 		*tmp16 |= 0x10;
-// 	else if ((ledvalue & LED2_USB3_MASK) == 0) /* LED2_USB3 */
-// 		*tmp16 |= 0x20;
-
-// 	ax88179_write_cmd(dev, AX_ACCESS_MAC, 0x73, 1, 1, tmp16);
-
-// 	kfree(tmp);
+/// Reads the LED mode from the EEPROM, else the eFuse, else the legacy preset.
+unsafe fn ax88179_led_mode(dev: *mut usbnet) -> DriverResult<u16> {
+    let ledvalue = match ax88179_check_nvm(dev) {
+        nvm::Nvm::Eeprom => Some(u16::from_le_bytes(ax88179_srom_read(dev, 0x42)?)),
+        nvm::Nvm::Efuse(efuse) => Some(efuse.led_mode()),
+        nvm::Nvm::Neither => None,
+    };
 
-// 	return 0;
-// }
+    match ledvalue {
+        Some(ledvalue) if led::led_mode_valid(ledvalue) => Ok(ledvalue),
+        /* load internal ROM for default setting */
+        _ => ax88179_convert_old_led(dev),
+    }
+}
 
-// static int ax88179_AutoDetach(struct usbnet *dev, int in_pm)
-// {
-// 	u16 *tmp16;
-// 	u8 *tmp8;
-// 	int (*fnr)(struct usbnet *, u8, u16, u16, u16, void *, int);
-// 	int (*fnw)(struct usbnet *, u8, u16, u16, u16, void *);
+/// Programs the PHY's LED link / activity registers for `ledvalue`, returning the
+/// values written.
+fn ax88179_led_phy_setting(phy: &mut Phy, ledvalue: u16) -> DriverResult<led::LedRegs> {
+    let page = phy.page::<ExtPage2c>()?;
 
-// 	if (!in_pm) {
-// 		fnr = ax88179_read_cmd;
-// 		fnw = ax88179_write_cmd;
-// 	} else {
-// 		fnr = ax88179_read_cmd_nopm;
-// 		fnw = ax88179_write_cmd_nopm;
-// 	}
+    let regs = led::led_regs(
+        ledvalue,
+        page.read(EXT2C_LED_ACTIVE)?,
+        page.read(EXT2C_LED_LINK)?,
+    );
+    page.write(EXT2C_LED_ACTIVE, regs.active)?;
+    page.write(EXT2C_LED_LINK, regs.link)?;
 
-// 	tmp16 = kmalloc(3, GFP_KERNEL);
-// 	if (!tmp16)
-// 		return -ENOMEM;
+    Ok(regs)
+}
 
-// 	tmp8 = (u8*)(&tmp16[2]);
+/// Programs the LEDs for `ledvalue`, normally the mode read by ax88179_led_mode.
+unsafe fn ax88179_led_setting(phy: &mut Phy, ledvalue: u16) -> DriverResult<()> {
+    let ctx = *phy.ctx();
+    let mut status: u8 = 0;
 
-// 	if (fnr(dev, AX_ACCESS_EEPROM, 0x43, 1, 2, tmp16, 1) < 0) {
-// 		kfree(tmp16);
-// 		return 0;
-// 	}
+    /* Check AX88179 version. UA1 or UA2 */
+    ctx.read(
+        AX_ACCESS_MAC,
+        GENERAL_STATUS,
+        1,
+        1,
+        &mut status as *mut u8 as _,
+        0,
+    )?;
+
+    /* UA1 */
+    if status & AX_SECLD == 0 {
+        let gpio = AX_GPIO_CTRL_GPIO3EN | AX_GPIO_CTRL_GPIO2EN | AX_GPIO_CTRL_GPIO1EN;
+        ctx.write(AX_ACCESS_MAC, AX_GPIO_CTRL, 1, gpio)?;
+    }
 
-// 	if ((*tmp16 == 0xFFFF) || (!(*tmp16 & 0x0100))) {
-// 		kfree(tmp16);
-// 		return 0;
-// 	}
+    let regs = ax88179_led_phy_setting(phy, ledvalue)?;
 
-// 	/* Enable Auto Detach bit */
-// 	*tmp8 = 0;
-// 	fnr(dev, AX_ACCESS_MAC, AX_CLK_SELECT, 1, 1, tmp8, 0);
-// 	*tmp8 |= AX_CLK_SELECT_ULR;
-// 	fnw(dev, AX_ACCESS_MAC, AX_CLK_SELECT, 1, 1, tmp8);
+    /* LED full duplex setting */
+    ctx.write(AX_ACCESS_MAC, 0x73, 1, regs.duplex)?;
 
-// 	fnr(dev, AX_ACCESS_MAC, AX_PHYPWR_RSTCTL, 2, 2, tmp16, 1);
-// 	*tmp16 |= AX_PHYPWR_RSTCTL_AUTODETACH;
-// 	fnw(dev, AX_ACCESS_MAC, AX_PHYPWR_RSTCTL, 2, 2, tmp16);
+    Ok(())
+}
 
-// 	kfree(tmp16);
+/// Enables PHY auto detach if EEPROM configuration word 0x43 asks for it.
+#[allow(non_snake_case)]
+unsafe fn ax88179_AutoDetach(ctx: &CmdCtx) -> DriverResult<()> {
+    let mut tmp16: u16 = 0;
+    let mut tmp8: u8 = 0;
+
+    // No EEPROM, or nothing to do
+    if ctx
+        .read(AX_ACCESS_EEPROM, 0x43, 1, 2, &mut tmp16 as *mut u16 as _, 1)
+        .is_err()
+    {
+        return Ok(());
+    }
 
-// 	return 0;
-// }
+    if tmp16 == 0xffff || tmp16 & 0x0100 == 0 {
+        return Ok(());
+    }
+
+    /* Enable Auto Detach bit */
+    ctx.read(
+        AX_ACCESS_MAC,
+        AX_CLK_SELECT,
+        1,
+        1,
+        &mut tmp8 as *mut u8 as _,
+        0,
+    )?;
+    tmp8 |= AX_CLK_SELECT_ULR;
+    ctx.write(AX_ACCESS_MAC, AX_CLK_SELECT, 1, tmp8)?;
+
+    ctx.read(
+        AX_ACCESS_MAC,
+        AX_PHYPWR_RSTCTL,
+        2,
+        2,
+        &mut tmp16 as *mut u16 as _,
+        1,
+    )?;
+    tmp16 |= AX_PHYPWR_RSTCTL_AUTODETACH;
+    ctx.write(AX_ACCESS_MAC, AX_PHYPWR_RSTCTL, 2, tmp16)?;
+
+    Ok(())
+}
+
+/// Reads or writes a MAC address as three little endian EEPROM words from `offset`.
+unsafe fn access_eeprom_mac(
+    dev: *mut usbnet,
+    buf: &mut [u8; mac::ETH_ALEN],
+    offset: u8,
+    wflag: c_int,
+) -> DriverResult<()> {
+    let ctx = CmdCtx::new(dev)?;
 
-unsafe fn access_eeprom_mac(dev: *mut usbnet, buf: *mut u8, offset: u8, wflag: c_int) -> KernelResult<()> {
-    let tmp: *mut u16 = buf as *mut u16;
+    for (i, word) in buf.chunks_exact_mut(2).enumerate() {
+        let addr = (offset + i as u8) as u16;
 
-    for i in 0..(ETH_ALEN as u8 >> 1) {
         if wflag > 0 {
-            ax88179_write_cmd(dev, AX_ACCESS_EEPROM, (offset + i) as u16, 1, *tmp.offset(i as isize))?;
+            ctx.write(
+                AX_ACCESS_EEPROM,
+                addr,
+                1,
+                u16::from_le_bytes([word[0], word[1]]),
+            )?;
             // FIXME: mdelay is a macro so using msleep for now
             // mdelay(15);
             msleep(15);
         } else {
-            let result = ax88179_read_cmd(dev, AX_ACCESS_EEPROM, (offset + i) as u16, 1, 2, tmp.offset(i as isize) as _, 0);
+            let mut val: u16 = 0;
+            let result = ctx.read(AX_ACCESS_EEPROM, addr, 1, 2, &mut val as *mut u16 as _, 1);
             if let Err(e) = result {
-                println!("DEBUG: ax88179 - failed to read MAC address from EEPROM: {}", e.into_kernel_errno());
+                netif_dbg!(
+                    UsbNet::from_raw(dev),
+                    NETIF_MSG_PROBE,
+                    "failed to read MAC address from EEPROM: {e}"
+                );
                 return result;
             }
+            word.copy_from_slice(&val.to_le_bytes());
         }
     }
 
-    if wflag == 0 {
-        core::slice::from_raw_parts_mut((*(*dev).net).dev_addr, ETH_ALEN as usize)
-            .copy_from_slice(core::slice::from_raw_parts_mut(buf, ETH_ALEN as usize))
-    } else {
+    if wflag > 0 {
         /* reload eeprom data */
-        ax88179_write_cmd(dev, AX_RELOAD_EEPROM_EFUSE, 0, 0, ())?;
+        ctx.write(AX_RELOAD_EEPROM_EFUSE, 0, 0, ())?;
     }
 
     Ok(())
 }
 
-unsafe fn ax88179_check_ether_addr(dev: *mut usbnet) -> c_int
-{
-	let tmp = core::slice::from_raw_parts_mut((*(*dev).net).dev_addr as *mut u8, ETH_ALEN as usize);
-	let default_mac: [u8; 6] = [0, 0x0e, 0xc6, 0x81, 0x79, 0x01];
-	let default_mac_178a: [u8; 6] = [0, 0x0e, 0xc6, 0x81, 0x78, 0x01];
-
-    // TODO: missing bindings to is_valid_ether_addr and eth_hw_addr_random
-
-	// if (tmp[0] == 0 && tmp[1] == 0 && tmp[2] == 0) ||
-    //     !is_valid_ether_addr(tmp.as_mut_ptr()) ||
-    //     tmp == &default_mac ||
-    //     tmp == default_mac_178a {
-	// 	let i;
-
-    //     // TODO: maybe format address better? See printk calls below.
-	// 	println!("Found invalid EEPROM MAC address value: {tmp:x}");
-
-	// 	// for i in 0..ETH_ALEN {
-	// 	// 	printk("%02X", *((u8*)tmp + i));
-	// 	// 	if (i != 5)
-	// 	// 		printk("-");
-	// 	// }
-	// 	// printk("\n");
-	// 	eth_hw_addr_random(*dev.net);
-
-	// 	*tmp = 0;
-	// 	*(tmp + 1) = 0x0E;
-	// 	*(tmp + 2) = 0xC6;
-	// 	*(tmp + 3) = 0x8E;
-
-	// 	return -(EADDRNOTAVAIL as c_int);
-	// }
-	return 0;
-}
-
-unsafe fn ax88179_get_mac(dev: *mut usbnet, buf: *mut u8) -> KernelResult<()> {
-    access_eeprom_mac(dev, buf, 0x0, 0)?;
-
-    // TODO: enable
-    // if ax88179_check_ether_addr(dev) != 0 {
-    //     ret = access_eeprom_mac(dev, (*(*dev).net).dev_addr, 0x0, 1);
-    //     if ret < 0 {
-    //         // netdev_err(dev->net, "Failed to write MAC to EEPROM: %d", ret);
-    //         println!("ERROR: ax88179 - failed to write MAC to EEPROM: {ret}");
-    //         return ret;
-    //     }
-
-    //     msleep(5);
-
-    //     ret = ax88179_read_cmd(dev, AX_ACCESS_MAC, AX_NODE_ID, ETH_ALEN, ETH_ALEN, buf, 0);
-    //     if ret < 0 {
-    //         // netdev_err(dev->net, "Failed to read MAC address: %d", ret);
-    //         println!("ERROR: ax88179 - failed to read MAC address: {ret}");
-    //         return ret;
-    //     }
-
-    //     for i in 0..ETH_ALEN {
-    //         if *((*(*dev).net).dev_addr.offset(i)) != *(buf.offset(i)) {
-    //             // netdev_warn(dev->net, "Found invalid EEPROM part or non-EEPROM");
-    //             println!("ERROR: ax88179 - found invalid EEPROM part or non-EEPROM");
-    //             break;
-    //         }
-    //     }
-    // }
-
-    (*(*dev).net).perm_addr[..ETH_ALEN as usize]
-        .copy_from_slice(core::slice::from_raw_parts_mut((*(*dev).net).dev_addr as *mut u8, ETH_ALEN as usize));
-
-    let result = ax88179_write_cmd(
-        dev,
+/// Reads the MAC address held by `source`. Errors mean the source isn't available.
+unsafe fn ax88179_read_mac(
+    dev: *mut usbnet,
+    source: mac::MacSource,
+    nvm: &nvm::Nvm,
+) -> DriverResult<[u8; mac::ETH_ALEN]> {
+    let ctx = CmdCtx::new(dev)?;
+
+    let mut addr = [0u8; mac::ETH_ALEN];
+
+    match source {
+        mac::MacSource::Firmware => {
+            let ret = eth_platform_get_mac_address(&mut (*(*dev).udev).dev, addr.as_mut_ptr());
+            KernelResult::from_kernel_errno(ret)?;
+        }
+        mac::MacSource::Efuse => match nvm {
+            nvm::Nvm::Efuse(efuse) => addr = efuse.node_id(),
+            _ => return Err(Error::ENODEV.into()),
+        },
+        mac::MacSource::Eeprom => match nvm {
+            nvm::Nvm::Eeprom => access_eeprom_mac(dev, &mut addr, 0x0, 0)?,
+            _ => return Err(Error::ENODEV.into()),
+        },
+        mac::MacSource::NodeId => ctx.read(
+            AX_ACCESS_MAC,
+            AX_NODE_ID,
+            ETH_ALEN as u16,
+            ETH_ALEN as u16,
+            addr.as_mut_ptr() as _,
+            0,
+        )?,
+        mac::MacSource::Random => {
+            let mut random = [0u8; 3];
+            get_random_bytes(random.as_mut_ptr() as _, random.len() as _);
+            addr = mac::random_mac(random);
+        }
+    }
+
+    Ok(addr)
+}
+
+/// Sets the netdev's address from the first source holding a valid one, falling back
+/// to a random address. Returns where the address came from.
+unsafe fn ax88179_choose_mac(dev: *mut usbnet) -> mac::MacSource {
+    let nvm = ax88179_check_nvm(dev);
+    let net = UsbNet::from_raw(dev).net();
+
+    for &source in mac::MacSource::CHAIN.iter() {
+        let addr = match ax88179_read_mac(dev, source, &nvm) {
+            Ok(addr) => addr,
+            Err(_) => continue,
+        };
+
+        match mac::check_mac(&addr) {
+            Ok(()) => {
+                net.set_dev_addr(&addr);
+                return source;
+            }
+            Err(reason) => netdev_warn!(
+                UsbNet::from_raw(dev),
+                "ignoring invalid {source} MAC address {} ({reason})",
+                mac::MacAddr(&addr)
+            ),
+        }
+    }
+
+    // Can't fail
+    if let Ok(addr) = ax88179_read_mac(dev, mac::MacSource::Random, &nvm) {
+        net.set_dev_addr(&addr);
+    }
+    net.set_addr_assign_type(NET_ADDR_RANDOM as u8);
+
+    mac::MacSource::Random
+}
+
+/// Writes the netdev's address to the EEPROM, then reads it back through AX_NODE_ID
+/// to check it stuck.
+unsafe fn ax88179_persist_mac(dev: *mut usbnet) -> Result<(), mac::PersistMacError> {
+    let ctx = CmdCtx::new(dev)?;
+
+    let mut written = UsbNet::from_raw(dev).net().dev_addr();
+    let mut read = [0u8; mac::ETH_ALEN];
+
+    access_eeprom_mac(dev, &mut written, 0x0, 1)?;
+
+    msleep(5);
+
+    ctx.read(
         AX_ACCESS_MAC,
         AX_NODE_ID,
         ETH_ALEN as u16,
-        core::slice::from_raw_parts_mut((*(*dev).net).dev_addr as *mut u8, ETH_ALEN as usize),
-    );
+        ETH_ALEN as u16,
+        read.as_mut_ptr() as _,
+        0,
+    )?;
 
-    if let Err(e) = result {
-        // netdev_err(dev->net, "Failed to write MAC address: %d", ret);
-        println!("ERROR: ax88179 - failed to write MAC address: {}", e.to_kernel_errno());
+    if read != written {
+        return Err(mac::PersistMacError::Mismatch { written, read });
     }
 
-    result
+    Ok(())
 }
 
-unsafe fn try_ax88179_bind(dev: *mut usbnet, intf: *mut usb_interface) -> KernelResult<()> {
-    println!("ax88179_bind");
+unsafe fn ax88179_get_mac(dev: *mut usbnet) -> DriverResult<mac::MacSource> {
+    let ctx = CmdCtx::new(dev)?;
 
-    let data: *mut ax88179_data = transmute((&mut *dev).data.as_ptr());
+    let source = ax88179_choose_mac(dev);
+
+    if source == mac::MacSource::Random && persist_mac.get() != 0 {
+        // The random address works without this, so carry on either way
+        match ax88179_persist_mac(dev) {
+            Ok(()) => netdev_info!(UsbNet::from_raw(dev), "wrote MAC address to EEPROM"),
+            Err(e) => netdev_warn!(
+                UsbNet::from_raw(dev),
+                "failed to write MAC address to EEPROM: {e}"
+            ),
+        }
+    }
+
+    let net = UsbNet::from_raw(dev).net();
+    let mut addr = net.dev_addr();
+    net.set_perm_addr(&addr);
+
+    ctx.write(AX_ACCESS_MAC, AX_NODE_ID, ETH_ALEN as u16, &mut addr[..])?;
+
+    Ok(source)
+}
+
+unsafe fn try_ax88179_bind(dev: *mut usbnet, intf: &UsbInterface) -> DriverResult<Box<Ax88179>> {
+    let ctx = CmdCtx::new(dev)?;
 
     let mut tmp32: u32;
     let mut tmp16: u16;
     let mut tmp: u8;
-    let mut mac = [0u8; ETH_ALEN as usize];
-    let mut ret: c_int;
 
-    usbnet_get_endpoints(dev, intf);
+    usbnet_get_endpoints(dev, intf.as_ptr());
 
-    // if (msg_enable != 0)
-    // 	dev->msg_enable = msg_enable;
+    if msg_enable.get() != 0 {
+        (*dev).msg_enable = msg_enable.get();
+    }
 
-    data.write(zeroed());
+    netif_dbg!(UsbNet::from_raw(dev), NETIF_MSG_PROBE, "ax88179_bind");
+
+    let mut ax = Ax88179::new(dev);
 
     tmp32 = 0;
-    ax88179_write_cmd(dev, 0x81, 0x310, 0, tmp32)?;
+    ctx.write(0x81, 0x310, 0, tmp32)?;
 
     /* Power up ethernet PHY */
     tmp16 = 0;
-    ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_PHYPWR_RSTCTL, 2, tmp16)?;
+    ctx.write(AX_ACCESS_MAC, AX_PHYPWR_RSTCTL, 2, tmp16)?;
     tmp16 = AX_PHYPWR_RSTCTL_IPRL;
-    ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_PHYPWR_RSTCTL, 2, tmp16)?;
+    ctx.write(AX_ACCESS_MAC, AX_PHYPWR_RSTCTL, 2, tmp16)?;
     msleep(200);
 
     tmp = AX_CLK_SELECT_ACS | AX_CLK_SELECT_BCS;
-    ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_CLK_SELECT, 1, tmp)?;
+    ctx.write(AX_ACCESS_MAC, AX_CLK_SELECT, 1, tmp)?;
     msleep(100);
 
     /* Get the MAC address */
-    ax88179_get_mac(dev, mac.as_mut_ptr())?;
+    let source = ax88179_get_mac(dev)?;
 
-    println!("Got mac value of {mac:?}");
+    netif_info!(
+        UsbNet::from_raw(dev),
+        NETIF_MSG_PROBE,
+        "MAC address {} from {source}",
+        mac::MacAddr(&UsbNet::from_raw(dev).net().dev_addr())
+    );
     // 	if (ret)
     // 		goto out;
 
@@ -1963,11 +2352,19 @@ unsafe fn try_ax88179_bind(dev: *mut usbnet, intf: *mut usb_interface) -> Kernel
     // 		       dev->net->dev_addr[4], dev->net->dev_addr[5]);
     // #endif
 
-    // 	/* RX bulk configuration, default for USB3.0 to Giga*/
-    // 	memcpy(mac, &AX88179_BULKIN_SIZE[0], 5);
-    // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_RX_BULKIN_QCTRL, 5, 5, mac);
+    /* RX bulk configuration, default for USB3.0 to Giga*/
+    let bulkin = AX88179_BULKIN_SIZE[0].with_overrides(
+        ax.bsize.load(Ordering::Relaxed),
+        ax.ifg.load(Ordering::Relaxed),
+    );
+    ctx.write(
+        AX_ACCESS_MAC,
+        AX_RX_BULKIN_QCTRL,
+        5,
+        &mut bulkin.to_bytes()[..],
+    )?;
 
-    // 	dev->rx_urb_size = 1024 * 20;
+    (*dev).rx_urb_size = bulkin.rx_urb_size() as _;
 
     // 	tmp = 0x34;
     // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_PAUSE_WATERLVL_LOW, 1, 1, &tmp);
@@ -1991,7 +2388,9 @@ unsafe fn try_ax88179_bind(dev: *mut usbnet, intf: *mut usb_interface) -> Kernel
     // 	dev->net->netdev_ops = &ax88179_netdev_ops;
     // #endif
 
-    // 	dev->net->ethtool_ops = &ax88179_ethtool_ops;
+    UsbNet::from_raw(dev)
+        .net()
+        .set_ethtool_ops(&ethtool::AX88179_ETHTOOL_OPS.0);
     // #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 30)
     // 	dev->net->needed_headroom = 8;
     // #endif
@@ -1999,75 +2398,53 @@ unsafe fn try_ax88179_bind(dev: *mut usbnet, intf: *mut usb_interface) -> Kernel
     // 	dev->net->max_mtu = 4088;
     // #endif
 
-    // 	/* Initialize MII structure */
-    // 	dev->mii.dev = dev->net;
-    // 	dev->mii.mdio_read = ax88179_mdio_read;
-    // 	dev->mii.mdio_write = ax88179_mdio_write;
-    // 	dev->mii.phy_id_mask = 0xff;
-    // 	dev->mii.reg_num_mask = 0xff;
-    // 	dev->mii.phy_id = 0x03;
-    // 	dev->mii.supports_gmii = 1;
+    /* Initialize MII structure */
+    (*dev).mii.dev = (*dev).net;
+    (*dev).mii.mdio_read = Some(ax88179_mdio_read);
+    (*dev).mii.mdio_write = Some(ax88179_mdio_write);
+    (*dev).mii.phy_id_mask = 0xff;
+    (*dev).mii.reg_num_mask = 0xff;
+    (*dev).mii.phy_id = AX88179_PHY_ID as _;
+    (*dev).mii.set_supports_gmii(1);
+
+    sysfs::ax88179_add_sysfs_group((*dev).net);
 
     // 	dev->net->features |= NETIF_F_IP_CSUM;
     // #if LINUX_VERSION_CODE > KERNEL_VERSION(2, 6, 22)
     // 	dev->net->features |= NETIF_F_IPV6_CSUM;
     // #endif
     // #if LINUX_VERSION_CODE >= KERNEL_VERSION(3, 12, 0)
-    // 	if (usb_device_no_sg_constraint(dev->udev))
-    // 		dev->can_dma_sg = 1;
-    // 	dev->net->features |= NETIF_F_SG | NETIF_F_TSO;
+    // 	dev->net->features |= NETIF_F_TSO;
     // #endif
+    ax88179_set_sg(&UsbNet::from_raw(dev));
 
     // #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 39)
     // 	dev->net->hw_features |= NETIF_F_IP_CSUM;
     // 	dev->net->hw_features |= NETIF_F_IPV6_CSUM;
-    // 	dev->net->hw_features |= NETIF_F_SG | NETIF_F_TSO;
+    // 	dev->net->hw_features |= NETIF_F_TSO;
     // #endif
 
-    // 	/* Enable checksum offload */
-    // 	tmp = AX_RXCOE_IP | AX_RXCOE_TCP | AX_RXCOE_UDP |
-    // 	      AX_RXCOE_TCPV6 | AX_RXCOE_UDPV6;
-    // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_RXCOE_CTL, 1, 1, &tmp);
-
-    // 	tmp = AX_TXCOE_IP | AX_TXCOE_TCP | AX_TXCOE_UDP |
-    // 	      AX_TXCOE_TCPV6 | AX_TXCOE_UDPV6;
-    // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_TXCOE_CTL, 1, 1, &tmp);
+    ax.write_config(&ctx)?;
 
-    // 	ax179_data->checksum |= AX_RX_CHECKSUM | AX_TX_CHECKSUM;
+    // The LEDs are cosmetic, don't fail over an unreadable EEPROM
+    ax.led_mode = ax88179_led_mode(dev).unwrap_or_else(|e| {
+        e.warn(&ctx.usbnet(), "reading LED mode");
+        led::convert_old_led(0xff)
+    });
 
-    // 	/* Configure RX control register => start operation */
-    // 	tmp16 = AX_RX_CTL_DROPCRCERR | AX_RX_CTL_START | AX_RX_CTL_AP |
-    // 		 AX_RX_CTL_AMALL | AX_RX_CTL_AB;
-    // 	if (NET_IP_ALIGN == 0)
-    // 		tmp16 |= AX_RX_CTL_IPE;
-    // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_RX_CTL, 2, 2, &tmp16);
+    {
+        let mut phy = Phy::with_ctx(&ax, ctx)?;
 
-    // 	tmp = AX_MONITOR_MODE_PMETYPE | AX_MONITOR_MODE_PMEPOL |
-    // 	      AX_MONITOR_MODE_RWMP;
-    // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_MONITOR_MODE, 1, 1, &tmp);
-
-    // 	ax88179_read_cmd(dev, AX_ACCESS_MAC, AX_MONITOR_MODE, 1, 1, &tmp, 0);
-    // #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 34)
-    // 		netdev_dbg(dev->net, "Monitor mode = 0x%02x\n", tmp);
-    // #else
-    // 		devdbg(dev, "Monitor mode = 0x%02x\n", tmp);
-    // #endif
-    // 	/* Configure default medium type => giga */
-    // 	tmp16 = AX_MEDIUM_RECEIVE_EN	 | AX_MEDIUM_TXFLOW_CTRLEN |
-    // 		AX_MEDIUM_RXFLOW_CTRLEN | AX_MEDIUM_FULL_DUPLEX   |
-    // 		AX_MEDIUM_GIGAMODE;
-
-    // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_MEDIUM_STATUS_MODE,
-    // 			  2, 2, &tmp16);
-
-    // 	ax88179_led_setting(dev);
+        if let Err(e) = ax88179_led_setting(&mut phy, ax.led_mode) {
+            e.warn(&ctx.usbnet(), "configuring LEDs");
+        }
 
-    // 	ax88179_EEE_setting(dev);
+        ax88179_eee_setting(&mut phy, ax.eee_advertisement())?;
 
-    // 	ax88179_Gether_setting(dev);
+        ax88179_gether_setting(&mut phy, ax.geth.load(Ordering::Relaxed))?;
+    }
 
-    // 	ax88179_read_cmd(dev, AX_ACCESS_MAC, AX_MONITOR_MODE, 1, 1, &tmp, 0);
-    // 	ax179_data->reg_monitor = tmp;
+    ax.save(&ctx)?;
 
     // 	/* Restart autoneg */
     // 	mii_nway_restart(&dev->mii);
@@ -2082,15 +2459,15 @@ unsafe fn try_ax88179_bind(dev: *mut usbnet, intf: *mut usb_interface) -> Kernel
     // #endif
     // 	return 0;
 
-    Ok(())
-}
-
-unsafe extern "C" fn ax88179_bind(dev: *mut usbnet, intf: *mut usb_interface) -> c_int {
-    try_ax88179_bind(dev, intf).into_kernel_errno()
+    Ok(ax)
 }
 
-unsafe extern "C" fn ax88179_unbind(dev: *mut usbnet, intf: *mut usb_interface) {
-    println!("ax88179_unbind");
+fn ax88179_unbind(ax: Box<Ax88179>) {
+    netif_dbg!(
+        unsafe { UsbNet::from_raw(ax.dev) },
+        NETIF_MSG_PROBE,
+        "ax88179_unbind"
+    );
     // 	u16 *tmp16;
     // 	u8 *tmp8;
     // 	struct ax88179_data *ax179_data = (struct ax88179_data *) dev->data;
@@ -2119,323 +2496,307 @@ unsafe extern "C" fn ax88179_unbind(dev: *mut usbnet, intf: *mut usb_interface)
     // 	kfree(tmp16);
 }
 
-// static void
-// ax88179_rx_checksum(struct sk_buff *skb, u32 *pkt_hdr)
-// {
-// 	skb->ip_summed = CHECKSUM_NONE;
+fn ax88179_rx_checksum(skb: &mut SkBuff, pkt_hdr: u32) {
+    skb.set_ip_summed(CHECKSUM_NONE);
 
-// 	/* checksum error bit is set */
-// 	if ((*pkt_hdr & AX_RXHDR_L3CSUM_ERR) ||
-// 	    (*pkt_hdr & AX_RXHDR_L4CSUM_ERR))
-// 		return;
-
-// 	/* It must be a TCP or UDP packet with a valid checksum */
-// 	if (((*pkt_hdr & AX_RXHDR_L4_TYPE_MASK) == AX_RXHDR_L4_TYPE_TCP) ||
-// 	    ((*pkt_hdr & AX_RXHDR_L4_TYPE_MASK) == AX_RXHDR_L4_TYPE_UDP))
-// 		skb->ip_summed = CHECKSUM_UNNECESSARY;
-// }
+    /* checksum error bit is set */
+    if pkt_hdr & (AX_RXHDR_L3CSUM_ERR | AX_RXHDR_L4CSUM_ERR) != 0 {
+        return;
+    }
 
-unsafe extern "C" fn ax88179_rx_fixup(dev: *mut usbnet, skb: *mut sk_buff) -> c_int {
-    // 	struct sk_buff *ax_skb = NULL;
-    // 	int pkt_cnt = 0;
-    // 	u32 rx_hdr = 0;
-    // 	u16 hdr_off = 0;
-    // 	u32 *pkt_hdr = NULL;
+    /* It must be a TCP or UDP packet with a valid checksum */
+    match pkt_hdr & AX_RXHDR_L4_TYPE_MASK {
+        AX_RXHDR_L4_TYPE_TCP | AX_RXHDR_L4_TYPE_UDP => skb.set_ip_summed(CHECKSUM_UNNECESSARY),
+        _ => {}
+    }
+}
 
-    // 	if (skb->len == 0) {
-    // #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 34)
-    // 		netdev_err(dev->net, "RX SKB length zero");
-    // #else
-    // 		deverr(dev, "RX SKB length zero");
-    // #endif
-    // 		dev->net->stats.rx_errors++;
-    //     return 0;
-    // }
+// GFP_ATOMIC is a macro, (__GFP_HIGH | __GFP_ATOMIC | __GFP_KSWAPD_RECLAIM)
+const GFP_ATOMIC: gfp_t = 0xa20;
 
-    // 	skb_trim(skb, skb->len - 4);
-    // #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 22)
-    // 	memcpy(&rx_hdr, skb_tail_pointer(skb), sizeof(rx_hdr));
-    // #else
-    // 	memcpy(&rx_hdr, skb->tail, sizeof(rx_hdr));
-    // #endif
-    // 	le32_to_cpus(&rx_hdr);
+fn read_le32(buf: &[u8], offset: usize) -> DriverResult<u32> {
+    match buf.get(offset..offset + 4) {
+        Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err(Error::EINVAL.into()),
+    }
+}
 
-    // 	pkt_cnt = (u16)rx_hdr;
-    // 	hdr_off = (u16)(rx_hdr >> 16);
-    // 	pkt_hdr = (u32 *)(skb->data + hdr_off);
+/// Cuts an skb whose data starts at a frame down to the frame itself, as the stack
+/// expects it.
+fn ax88179_rx_frame(skb: &mut SkBuff, pkt_len: u32, pkt_hdr: u32) -> DriverResult<()> {
+    skb.trim(pkt_len)?;
 
-    // 	while (pkt_cnt--) {
-    // 		u16 pkt_len;
+    /* Skip IP alignment psudo header */
+    if NET_IP_ALIGN == 0 {
+        skb.pull(2)?;
+    }
 
-    // 		le32_to_cpus(pkt_hdr);
-    // 		pkt_len = (*pkt_hdr >> 16) & 0x1fff;
+    skb.set_truesize(skb.len() + size_of::<sk_buff>() as u32);
+    ax88179_rx_checksum(skb, pkt_hdr);
 
-    // 		/* Check CRC or runt packet */
-    // 		if ((*pkt_hdr & AX_RXHDR_CRC_ERR) ||
-    // 		    (*pkt_hdr & AX_RXHDR_DROP_ERR)) {
-    // 			skb_pull(skb, (pkt_len + 7) & 0xFFF8);
-    // 			pkt_hdr++;
-    // 			continue;
-    // 		}
+    Ok(())
+}
 
-    // 		if (pkt_cnt == 0) {
-    // 			skb->len = pkt_len;
+/// Splits a bulk-in transfer into its frames. The chip appends a header per frame,
+/// located by the rx_hdr word in the last four bytes. All frames but the last are
+/// passed up as clones, the last one is left in `skb` for usbnet.
+fn try_ax88179_rx_fixup(dev: &UsbNet, skb: &mut SkBuff) -> DriverResult<()> {
+    let len = skb.len();
 
-    // 			/* Skip IP alignment psudo header */
-    // 			if (NET_IP_ALIGN == 0)
-    // 				skb_pull(skb, 2);
+    if len < 4 {
+        netif_err_ratelimited!(*dev, NETIF_MSG_RX_ERR, "RX SKB too short: {len}");
+        return Err(Error::EINVAL.into());
+    }
 
-    // #if LINUX_VERSION_CODE < KERNEL_VERSION(2, 6, 22)
-    // 			skb->tail = skb->data + skb->len;
-    // #else
-    // 			skb_set_tail_pointer(skb, skb->len);
-    // #endif
-    // 			skb->truesize = skb->len + sizeof(struct sk_buff);
-    // 			ax88179_rx_checksum(skb, pkt_hdr);
+    let rx_hdr = read_le32(skb.data(), len as usize - 4)?;
+    skb.trim(len - 4)?;
 
-    // 			return 1;
-    // 		}
+    let pkt_cnt = rx_hdr as u16;
+    let hdr_off = (rx_hdr >> 16) as usize;
 
-    // #ifndef RX_SKB_COPY
-    // 		ax_skb = skb_clone(skb, GFP_ATOMIC);
-    // #else
-    // 		ax_skb = alloc_skb(pkt_len + NET_IP_ALIGN, GFP_ATOMIC);
-    // 		skb_reserve(ax_skb, NET_IP_ALIGN);
-    // #endif
+    // skb stays untouched until the last frame, so the headers and frames are found
+    // relative to its data
+    let mut offset = 0;
 
-    // 		if (ax_skb) {
-    // #ifndef RX_SKB_COPY
-    // 			ax_skb->len = pkt_len;
+    for i in 0..pkt_cnt {
+        let pkt_hdr = read_le32(skb.data(), hdr_off + 4 * i as usize)?;
+        let pkt_len = (pkt_hdr >> 16) & 0x1fff;
 
-    // 			/* Skip IP alignment psudo header */
-    // 			if (NET_IP_ALIGN == 0)
-    // 				skb_pull(ax_skb, 2);
+        /* Check CRC or runt packet */
+        if pkt_hdr & (AX_RXHDR_CRC_ERR | AX_RXHDR_DROP_ERR) == 0 {
+            if i == pkt_cnt - 1 {
+                skb.pull(offset)?;
+                return ax88179_rx_frame(skb, pkt_len, pkt_hdr);
+            }
 
-    // #if LINUX_VERSION_CODE < KERNEL_VERSION(2, 6, 22)
-    // 			ax_skb->tail = ax_skb->data + ax_skb->len;
-    // #else
-    // 			skb_set_tail_pointer(ax_skb, ax_skb->len);
-    // #endif
+            let mut ax_skb = skb.clone(GFP_ATOMIC)?;
+            ax_skb.pull(offset)?;
+            ax88179_rx_frame(&mut ax_skb, pkt_len, pkt_hdr)?;
+            dev.skb_return(ax_skb);
+        }
 
-    // #else
-    // 			skb_put(ax_skb, pkt_len);
-    // 			memcpy(ax_skb->data, skb->data, pkt_len);
+        offset += (pkt_len + 7) & 0xfff8;
+    }
 
-    // 			if (NET_IP_ALIGN == 0)
-    // 				skb_pull(ax_skb, 2);
-    // #endif
-    // 			ax_skb->truesize = ax_skb->len + sizeof(struct sk_buff);
-    // 			ax88179_rx_checksum(ax_skb, pkt_hdr);
-    // 			usbnet_skb_return(dev, ax_skb);
-    // 		} else {
-    // 			return 0;
-    // 		}
-
-    // 		skb_pull(skb, (pkt_len + 7) & 0xFFF8);
-    // 		pkt_hdr++;
-    // 	}
-    return 1;
+    // Nothing left for usbnet to pass up
+    Err(Error::EINVAL.into())
 }
 
-unsafe extern "C" fn ax88179_tx_fixup(
-    dev: *mut usbnet,
-    skb: *mut sk_buff,
-    flags: gfp_t,
-) -> *mut sk_buff {
-    println!("ax88179_tx_fixup");
-    // 	u32 tx_hdr1 = 0, tx_hdr2 = 0;
-    // 	int frame_size = dev->maxpacket;
-    // #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 24)
-    // 	int mss = skb_shinfo(skb)->gso_size;
-    // #else
-    // 	int mss = 0;
-    // #endif
-    // 	int headroom = 0;
-    // 	int tailroom = 0;
+/* Length of the two little-endian header words prepended to every TX frame */
+const AX_TX_HDR_LEN: u32 = 8;
 
-    // 	tx_hdr1 = skb->len;
-    // 	tx_hdr2 = mss;
-    // 	if (((skb->len + 8) % frame_size) == 0)
-    // 		tx_hdr2 |= 0x80008000;	/* Enable padding */
-    // #if LINUX_VERSION_CODE >= KERNEL_VERSION(3, 12, 0)
-    // 	if (!dev->can_dma_sg && (dev->net->features & NETIF_F_SG) &&
-    // 	    skb_linearize(skb))
-    // 		return NULL;
-    // #elif LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 24)
-    // 	if ((dev->net->features & NETIF_F_SG) && skb_linearize(skb))
-    // 		return NULL;
-    // #endif
+/// Advertises scatter-gather TX, letting usbnet submit paged skbs directly when the
+/// host controller has no SG constraint.
+fn ax88179_set_sg(dev: &UsbNet) {
+    if dev.no_sg_constraint() {
+        dev.set_can_dma_sg(true);
+    }
 
-    // 	headroom = skb_headroom(skb);
-    // 	tailroom = skb_tailroom(skb);
+    let net = dev.net();
+    net.set_features(net.features() | NETIF_F_SG);
+    net.set_hw_features(net.hw_features() | NETIF_F_SG);
+}
 
-    // 	if ((headroom + tailroom) >= 8) {
-    // 		if (headroom < 8) {
-    // 			skb->data = memmove(skb->head + 8, skb->data, skb->len);
-    // #if LINUX_VERSION_CODE < KERNEL_VERSION(2, 6, 22)
-    // 			skb->tail = skb->data + skb->len;
-    // #else
-    // 			skb_set_tail_pointer(skb, skb->len);
-    // #endif
-    // 		}
-    // 	} else {
-    // 		struct sk_buff *skb2 = NULL;
-    // 		skb2 = skb_copy_expand(skb, 8, 0, flags);
-    // 		dev_kfree_skb_any(skb);
-    // 		skb = skb2;
-    // 		if (!skb)
-    // 			return NULL;
-    // 	}
+/// The two TX header words: frame length, then MSS with the padding flags set when the
+/// frame plus headers would end exactly on a USB packet boundary.
+fn ax88179_tx_headers(len: u32, mss: u32, maxpacket: u32) -> (u32, u32) {
+    let tx_hdr1 = len;
+    let mut tx_hdr2 = mss;
 
-    // 	skb_push(skb, 4);
-    // 	cpu_to_le32s(&tx_hdr2);
-    // #if LINUX_VERSION_CODE < KERNEL_VERSION(2, 6, 22)
-    // 	memcpy(skb->data, &tx_hdr2, 4);
-    // #else
-    // 	skb_copy_to_linear_data(skb, &tx_hdr2, 4);
-    // #endif
+    if maxpacket != 0 && (len + AX_TX_HDR_LEN) % maxpacket == 0 {
+        tx_hdr2 |= 0x80008000; /* Enable padding */
+    }
 
-    // 	skb_push(skb, 4);
-    // 	cpu_to_le32s(&tx_hdr1);
-    // #if LINUX_VERSION_CODE < KERNEL_VERSION(2, 6, 22)
-    // 	memcpy(skb->data, &tx_hdr1, 4);
-    // #else
-    // 	skb_copy_to_linear_data(skb, &tx_hdr1, 4);
-    // #endif
+    (tx_hdr1, tx_hdr2)
+}
 
-    return skb;
+/// Paged skbs can only be handed to usbnet as-is when the host controller does SG.
+/// Otherwise, with SG advertised to the stack, the fragments must be pulled into the
+/// linear area first.
+fn ax88179_tx_needs_linearize(can_dma_sg: bool, sg_enabled: bool, nonlinear: bool) -> bool {
+    nonlinear && sg_enabled && !can_dma_sg
 }
 
-unsafe extern "C" fn ax88179_link_reset(dev: *mut usbnet) -> c_int {
-    println!("ax88179_link_reset");
-    // 	struct ax88179_data *data = (struct ax88179_data *)&dev->data;
-    // 	u8 *tmp, *link_sts, *tmp_16;
-    // 	u16 *mode, *tmp16, delay = 10 * HZ;
-    // 	u32 *tmp32;
-    // 	unsigned long jtimeout = 0;
+fn try_ax88179_tx_fixup(dev: &UsbNet, mut skb: SkBuff, flags: gfp_t) -> DriverResult<SkBuff> {
+    let (tx_hdr1, tx_hdr2) = ax88179_tx_headers(skb.len(), skb.gso_size(), dev.maxpacket());
 
-    // 	tmp_16 = kmalloc(16, GFP_KERNEL);
-    // 	if (!tmp_16)
-    // 		return -ENOMEM;
-    // 	tmp = (u8*)tmp_16;
-    // 	link_sts = (u8*)(&tmp_16[5]);
-    // 	mode = (u16*)(&tmp_16[6]);
-    // 	tmp16 = (u16*)(&tmp_16[8]);
-    // 	tmp32 = (u32*)(&tmp_16[10]);
+    if ax88179_tx_needs_linearize(
+        dev.can_dma_sg(),
+        dev.net().features() & NETIF_F_SG != 0,
+        skb.is_nonlinear(),
+    ) {
+        skb.linearize()?;
+    }
 
-    // 	*mode = AX_MEDIUM_TXFLOW_CTRLEN | AX_MEDIUM_RXFLOW_CTRLEN;
+    // As in the C driver, a frame short on headroom for the TX headers is moved back
+    // into its tailroom where it fits. Failing that only the linear header is
+    // reallocated, also if it's shared with a clone; any paged data stays attached to
+    // the skb for the host controller to DMA.
+    if skb.headroom() < AX_TX_HDR_LEN && skb.headroom() + skb.tailroom() >= AX_TX_HDR_LEN {
+        // Fails for a shared header, which cow_head copies below
+        let _ = skb.move_data(AX_TX_HDR_LEN);
+    }
+    skb.cow_head(AX_TX_HDR_LEN, flags)?;
 
-    // 	ax88179_read_cmd(dev, AX_ACCESS_MAC, PHYSICAL_LINK_STATUS,
-    // 			 1, 1, link_sts, 0);
+    skb.push(4)?.copy_from_slice(&tx_hdr2.to_le_bytes());
+    skb.push(4)?.copy_from_slice(&tx_hdr1.to_le_bytes());
 
-    // 	jtimeout = jiffies + delay;
-    // 	while(time_before(jiffies, jtimeout)) {
+    Ok(skb)
+}
 
-    // 		ax88179_read_cmd(dev, AX_ACCESS_PHY, AX88179_PHY_ID, GMII_PHY_PHYSR, 2, tmp16, 1);
+/// A deadline in jiffies, standing in for the C driver's `time_after(jiffies, timeout)` loops
+struct Timeout(c_ulong);
 
-    // 		if (*tmp16 & GMII_PHY_PHYSR_LINK) {
-    // 			break;
-    // 		}
-    // 	}
+impl Timeout {
+    fn from_msecs(msecs: u32) -> Self {
+        unsafe { Self(core::ptr::read_volatile(&jiffies).wrapping_add(__msecs_to_jiffies(msecs))) }
+    }
 
-    // 	if (!(*tmp16 & GMII_PHY_PHYSR_LINK))
-    // 		return 0;
-    // 	else if (GMII_PHY_PHYSR_GIGA == (*tmp16 & GMII_PHY_PHYSR_SMASK)) {
-    // 		*mode |= AX_MEDIUM_GIGAMODE;
-    // 		if (dev->net->mtu > 1500)
-    // 			*mode |= AX_MEDIUM_JUMBO_EN;
-
-    // 		if (*link_sts & AX_USB_SS)
-    // 			memcpy(tmp, &AX88179_BULKIN_SIZE[0], 5);
-    // 		else if (*link_sts & AX_USB_HS)
-    // 			memcpy(tmp, &AX88179_BULKIN_SIZE[1], 5);
-    // 		else
-    // 			memcpy(tmp, &AX88179_BULKIN_SIZE[3], 5);
-    // 	} else if (GMII_PHY_PHYSR_100 == (*tmp16 & GMII_PHY_PHYSR_SMASK)) {
-    // 		*mode |= AX_MEDIUM_PS;	/* Bit 9 : PS */
-    // 		if (*link_sts & (AX_USB_SS | AX_USB_HS))
-    // 			memcpy(tmp, &AX88179_BULKIN_SIZE[2], 5);
-    // 		else
-    // 			memcpy(tmp, &AX88179_BULKIN_SIZE[3], 5);
-    // 	} else
-    // 		memcpy(tmp, &AX88179_BULKIN_SIZE[3], 5);
-
-    // 	if (bsize != -1) {
-    // 		if (bsize > 24)
-    // 			bsize = 24;
-
-    // 		else if (bsize == 0) {
-    // 			tmp[1] = 0;
-    // 			tmp[2] = 0;
-    // 		}
-
-    // 		tmp[3] = (u8)bsize;
-    // 	}
+    fn expired(&self) -> bool {
+        let now = unsafe { core::ptr::read_volatile(&jiffies) };
+        (self.0.wrapping_sub(now) as c_long) < 0
+    }
+}
 
-    // 	if (ifg != -1) {
-    // 		if (ifg > 255)
-    // 			ifg = 255;
-    // 		tmp[4] = (u8)ifg;
-    // 	}
+fn try_ax88179_link_reset(ax: &Ax88179, dev: &UsbNet) -> DriverResult<()> {
+    // usbnet only calls the hooks for the device bind set up
+    let ctx = unsafe { CmdCtx::new(dev.as_ptr())? };
+
+    netif_dbg!(*dev, NETIF_MSG_LINK, "ax88179_link_reset");
+
+    let mut mode: u16 = AX_MEDIUM_TXFLOW_CTRLEN | AX_MEDIUM_RXFLOW_CTRLEN;
+    let mut link_sts: u8 = 0;
+    let mut physr: u16 = 0;
+    let mut tmp32: u32 = 0;
+
+    // EEE / green ethernet changed through sysfs. Both only take effect after
+    // renegotiation, so restart it and configure the MAC for the link it comes back
+    // up with below.
+    if ax.phy_settings_changed.swap(false, Ordering::Acquire) {
+        {
+            let mut phy = unsafe { Phy::with_ctx(ax, ctx)? };
+            ax88179_eee_setting(&mut phy, ax.eee_advertisement())?;
+            ax88179_gether_setting(&mut phy, ax.geth.load(Ordering::Relaxed))?;
+        }
 
-    // 	/* RX bulk configuration */
-    // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_RX_BULKIN_QCTRL, 5, 5, tmp);
+        // Goes through ax88179_mdio_*, so only once the PHY is released
+        dev.mii_nway_restart();
+    }
 
-    // 	if (*tmp16 & GMII_PHY_PHYSR_FULL)
-    // 		*mode |= AX_MEDIUM_FULL_DUPLEX;	/* Bit 1 : FD */
-    // 	dev->rx_urb_size = (1024 * (tmp[3] + 2));
+    unsafe {
+        ctx.read(
+            AX_ACCESS_MAC,
+            PHYSICAL_LINK_STATUS,
+            1,
+            1,
+            &mut link_sts as *mut u8 as _,
+            0,
+        )?;
+    }
 
-    // #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 34)
-    // 		netdev_info(dev->net, "Write medium type: 0x%04x\n", *mode);
-    // #else
-    // 		devinfo(dev, "Write medium type: 0x%04x\n", *mode);
-    // #endif
+    let timeout = Timeout::from_msecs(10_000);
+    while !timeout.expired() {
+        unsafe {
+            ctx.read(
+                AX_ACCESS_PHY,
+                AX88179_PHY_ID,
+                GMII_PHY_PHYSR,
+                2,
+                &mut physr as *mut u16 as _,
+                1,
+            )?;
+        }
 
-    // 	ax88179_read_cmd(dev, 0x81, 0x8c, 0, 4, tmp32, 1);
-    // 	delay = HZ / 2;
-    // 	if (*tmp32 & 0x40000000) {
+        if physr & GMII_PHY_PHYSR_LINK != 0 {
+            break;
+        }
+    }
 
-    // 		u16 *tmp1 = (u16*)(&tmp_16[14]);
-    // 		ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_RX_CTL, 2, 2, tmp1);
+    if physr & GMII_PHY_PHYSR_LINK == 0 {
+        return Ok(());
+    }
 
-    // 		/* Configure default medium type => giga */
-    // 		ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_MEDIUM_STATUS_MODE,
-    // 				  2, 2, mode);
+    let bulkin = match physr & GMII_PHY_PHYSR_SMASK {
+        GMII_PHY_PHYSR_GIGA => {
+            mode |= AX_MEDIUM_GIGAMODE;
+            if dev.net().mtu() > 1500 {
+                mode |= AX_MEDIUM_JUMBO_EN;
+            }
 
-    // 		jtimeout = jiffies + delay;
+            if link_sts & AX_USB_SS != 0 {
+                AX88179_BULKIN_SIZE[0]
+            } else if link_sts & AX_USB_HS != 0 {
+                AX88179_BULKIN_SIZE[1]
+            } else {
+                AX88179_BULKIN_SIZE[3]
+            }
+        }
+        GMII_PHY_PHYSR_100 => {
+            mode |= AX_MEDIUM_PS; /* Bit 9 : PS */
+            if link_sts & (AX_USB_SS | AX_USB_HS) != 0 {
+                AX88179_BULKIN_SIZE[2]
+            } else {
+                AX88179_BULKIN_SIZE[3]
+            }
+        }
+        _ => AX88179_BULKIN_SIZE[3],
+    }
+    .with_overrides(
+        ax.bsize.load(Ordering::Relaxed),
+        ax.ifg.load(Ordering::Relaxed),
+    );
 
-    // 		while (time_before(jiffies, jtimeout)) {
+    /* RX bulk configuration */
+    ctx.write(
+        AX_ACCESS_MAC,
+        AX_RX_BULKIN_QCTRL,
+        5,
+        &mut bulkin.to_bytes()[..],
+    )?;
 
-    // 			ax88179_read_cmd(dev, 0x81, 0x8c, 0, 4, tmp32, 1);
+    if physr & GMII_PHY_PHYSR_FULL != 0 {
+        mode |= AX_MEDIUM_FULL_DUPLEX; /* Bit 1 : FD */
+    }
+    dev.set_rx_urb_size(bulkin.rx_urb_size());
 
-    // 			if (!(*tmp32 & 0x40000000))
-    // 				break;
+    netif_dbg!(*dev, NETIF_MSG_LINK, "write medium type {mode:#06x}");
 
-    // 			*tmp32 = 0x80000000;
-    // 			ax88179_write_cmd(dev, 0x81, 0x8c, 0, 4, tmp32);
-    // 		}
+    unsafe { ctx.read(0x81, 0x8c, 0, 4, &mut tmp32 as *mut u32 as _, 1)? };
+    if tmp32 & 0x40000000 != 0 {
+        ctx.write(AX_ACCESS_MAC, AX_RX_CTL, 2, AX_RX_CTL_STOP)?;
 
-    // 		ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_RX_CTL,
-    // 				  2, 2, &data->rxctl);
-    // 	}
+        /* Configure default medium type => giga */
+        ctx.write(AX_ACCESS_MAC, AX_MEDIUM_STATUS_MODE, 2, mode)?;
 
-    // 	*mode |= AX_MEDIUM_RECEIVE_EN;
+        let timeout = Timeout::from_msecs(500);
+        while !timeout.expired() {
+            unsafe { ctx.read(0x81, 0x8c, 0, 4, &mut tmp32 as *mut u32 as _, 1)? };
 
-    // 	/* Configure default medium type => giga */
-    // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_MEDIUM_STATUS_MODE,
-    // 			  2, 2, mode);
-    // 	mii_check_media(&dev->mii, 1, 1);
-    // #if LINUX_VERSION_CODE < KERNEL_VERSION(4, 0, 0)
-    // 	if (dev->mii.force_media)
-    // 		netif_carrier_on(dev->net);
-    // #endif
-    // 	kfree(tmp_16);
+            if tmp32 & 0x40000000 == 0 {
+                break;
+            }
+
+            ctx.write(0x81, 0x8c, 0, 0x80000000u32)?;
+        }
+
+        ctx.write(AX_ACCESS_MAC, AX_RX_CTL, 2, ax.rxctl)?;
+    }
 
-    return 0;
+    mode |= AX_MEDIUM_RECEIVE_EN;
+
+    /* Configure default medium type => giga */
+    ctx.write(AX_ACCESS_MAC, AX_MEDIUM_STATUS_MODE, 2, mode)?;
+    dev.mii_check_media();
+
+    Ok(())
 }
 
-unsafe extern "C" fn ax88179_reset(dev: *mut usbnet) -> c_int {
-    println!("ax88179_reset");
+fn try_ax88179_reset(ax: &Ax88179, dev: &UsbNet) -> DriverResult<()> {
+    netif_dbg!(*dev, NETIF_MSG_IFUP, "ax88179_reset");
+
+    // usbnet only calls the hooks for the device bind set up
+    let ctx = unsafe { CmdCtx::new(dev.as_ptr())? };
+
     // 	void *buf = NULL;
     // 	u16 *tmp16 = NULL;
     // 	u8 *tmp = NULL;
@@ -2465,8 +2826,8 @@ unsafe extern "C" fn ax88179_reset(dev: *mut usbnet) -> c_int {
     // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_CLK_SELECT, 1, 1, tmp);
     // 	msleep(100);
 
-    // 	/* Ethernet PHY Auto Detach*/
-    // 	ax88179_AutoDetach(dev, 0);
+    /* Ethernet PHY Auto Detach*/
+    unsafe { ax88179_AutoDetach(&ctx)? };
 
     // 	/* Set the MAC address */
     // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_NODE_ID, ETH_ALEN,
@@ -2502,58 +2863,28 @@ unsafe extern "C" fn ax88179_reset(dev: *mut usbnet) -> c_int {
     // 	dev->net->features |= NETIF_F_IPV6_CSUM;
     // #endif
     // #if LINUX_VERSION_CODE >= KERNEL_VERSION(3, 12, 0)
-    // 	if (usb_device_no_sg_constraint(dev->udev))
-    // 		dev->can_dma_sg = 1;
-    // 	dev->net->features |= NETIF_F_SG | NETIF_F_TSO;
+    // 	dev->net->features |= NETIF_F_TSO;
     // #endif
+    ax88179_set_sg(dev);
 
     // #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 39)
     // 	dev->net->hw_features |= NETIF_F_IP_CSUM;
     // 	dev->net->hw_features |= NETIF_F_IPV6_CSUM;
-    // 	dev->net->hw_features |= NETIF_F_SG | NETIF_F_TSO;
-    // #endif
-
-    // 	/* Enable checksum offload */
-    // 	*tmp = AX_RXCOE_IP | AX_RXCOE_TCP | AX_RXCOE_UDP |
-    // 	       AX_RXCOE_TCPV6 | AX_RXCOE_UDPV6;
-    // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_RXCOE_CTL, 1, 1, tmp);
-
-    // 	*tmp = AX_TXCOE_IP | AX_TXCOE_TCP | AX_TXCOE_UDP |
-    // 	       AX_TXCOE_TCPV6 | AX_TXCOE_UDPV6;
-    // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_TXCOE_CTL, 1, 1, tmp);
-
-    // 	ax179_data->checksum |= AX_RX_CHECKSUM | AX_TX_CHECKSUM;
-
-    // 	/* Configure RX control register => start operation */
-    // 	*tmp16 = AX_RX_CTL_DROPCRCERR | AX_RX_CTL_START | AX_RX_CTL_AP |
-    // 		 AX_RX_CTL_AMALL | AX_RX_CTL_AB;
-    // 	if (NET_IP_ALIGN == 0)
-    // 		*tmp16 |= AX_RX_CTL_IPE;
-    // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_RX_CTL, 2, 2, tmp16);
-
-    // 	*tmp = AX_MONITOR_MODE_PMETYPE | AX_MONITOR_MODE_PMEPOL |
-    // 						AX_MONITOR_MODE_RWMP;
-    // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_MONITOR_MODE, 1, 1, tmp);
-
-    // 	ax88179_read_cmd(dev, AX_ACCESS_MAC, AX_MONITOR_MODE, 1, 1, tmp, 0);
-    // #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 34)
-    // 	netdev_dbg(dev->net, "Monitor mode = 0x%02x\n", *tmp);
-    // #else
-    // 	devdbg(dev, "Monitor mode = 0x%02x\n", *tmp);
+    // 	dev->net->hw_features |= NETIF_F_TSO;
     // #endif
 
-    // 	/* Configure default medium type => giga */
-    // 	*tmp16 = AX_MEDIUM_TXFLOW_CTRLEN | AX_MEDIUM_RXFLOW_CTRLEN |
-    // 		 AX_MEDIUM_FULL_DUPLEX | AX_MEDIUM_GIGAMODE;
+    unsafe { ax.write_config(&ctx)? };
 
-    // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_MEDIUM_STATUS_MODE,
-    // 			  2, 2, tmp16);
+    let mut phy = unsafe { Phy::with_ctx(ax, ctx)? };
 
-    // 	ax88179_led_setting(dev);
+    // The LEDs are cosmetic, don't fail over them
+    if let Err(e) = unsafe { ax88179_led_setting(&mut phy, ax.led_mode) } {
+        e.warn(dev, "configuring LEDs");
+    }
 
-    // 	ax88179_EEE_setting(dev);
+    ax88179_eee_setting(&mut phy, ax.eee_advertisement())?;
 
-    // 	ax88179_Gether_setting(dev);
+    ax88179_gether_setting(&mut phy, ax.geth.load(Ordering::Relaxed))?;
 
     // 	/* Restart autoneg */
     // 	mii_nway_restart(&dev->mii);
@@ -2567,23 +2898,61 @@ unsafe extern "C" fn ax88179_reset(dev: *mut usbnet) -> c_int {
     // 	devdbg(dev, "mtu %d\n", dev->net->mtu);
     // #endif
 
-    return 0;
+    Ok(())
 }
 
-unsafe extern "C" fn ax88179_stop(dev: *mut usbnet) -> c_int {
-    // 	u16 *tmp16;
-    // 	tmp16 = kmalloc(2, GFP_KERNEL);
-    // 	if (!tmp16)
-    // 		return -ENOMEM;
+fn try_ax88179_stop(dev: &UsbNet) -> DriverResult<()> {
+    // usbnet only calls the hooks for the device bind set up
+    let ctx = unsafe { CmdCtx::new(dev.as_ptr())? };
 
-    // 	ax88179_read_cmd(dev, AX_ACCESS_MAC, AX_MEDIUM_STATUS_MODE,
-    // 			 2, 2, tmp16, 1);
-    // 	*tmp16 &= ~AX_MEDIUM_RECEIVE_EN;
-    // 	ax88179_write_cmd(dev, AX_ACCESS_MAC, AX_MEDIUM_STATUS_MODE,
-    // 			  2, 2, tmp16);
+    let mut tmp16: u16 = 0;
 
-    // 	kfree(tmp16);
-    return 0;
+    unsafe {
+        ctx.read(
+            AX_ACCESS_MAC,
+            AX_MEDIUM_STATUS_MODE,
+            2,
+            2,
+            &mut tmp16 as *mut u16 as _,
+            1,
+        )?;
+    }
+    tmp16 &= !AX_MEDIUM_RECEIVE_EN;
+    ctx.write(AX_ACCESS_MAC, AX_MEDIUM_STATUS_MODE, 2, tmp16)
+}
+
+impl UsbNetDriver for Ax88179 {
+    fn bind(dev: &UsbNet, intf: &UsbInterface) -> DriverResult<Box<Self>> {
+        unsafe { try_ax88179_bind(dev.as_ptr(), intf) }
+    }
+
+    fn unbind(self: Box<Self>, _dev: &UsbNet, _intf: &UsbInterface) {
+        ax88179_unbind(self)
+    }
+
+    fn status(&self, dev: &UsbNet, event: &[u8]) {
+        ax88179_int_status(dev, event)
+    }
+
+    fn link_reset(&self, dev: &UsbNet) -> DriverResult<()> {
+        try_ax88179_link_reset(self, dev)
+    }
+
+    fn reset(&self, dev: &UsbNet) -> DriverResult<()> {
+        try_ax88179_reset(self, dev)
+    }
+
+    fn stop(&self, dev: &UsbNet) -> DriverResult<()> {
+        try_ax88179_stop(dev)
+    }
+
+    fn rx_fixup(&self, dev: &UsbNet, skb: &mut SkBuff) -> DriverResult<()> {
+        try_ax88179_rx_fixup(dev, skb)
+    }
+
+    fn tx_fixup(&self, dev: &UsbNet, skb: SkBuff, flags: gfp_t) -> DriverResult<SkBuff> {
+        try_ax88179_tx_fixup(dev, skb, flags)
+    }
 }
 
 trait KernelResultExt {
@@ -2595,7 +2964,7 @@ impl KernelResultExt for KernelResult<()> {
     fn from_kernel_errno(errno: c_int) -> Self {
         match errno {
             0 => Ok(()),
-            _ => Err(Error::from_kernel_errno(errno))
+            _ => Err(Error::from_kernel_errno(errno)),
         }
     }
 
@@ -2607,206 +2976,110 @@ impl KernelResultExt for KernelResult<()> {
     }
 }
 
-#[allow(non_upper_case_globals)]
-static mut ax88179_info: MaybeUninit<driver_info> = MaybeUninit::uninit();
-
-// static const struct driver_info ax88178a_info = {
-// 	.description = "",
-// //	.description = "ASIX AX88178A USB 2.0 Gigabit Ethernet",
-// 	.bind	= ax88179_bind,
-// 	.unbind	= ax88179_unbind,
-// 	.status	= ax88179_status,
-// 	.link_reset = ax88179_link_reset,
-// 	.reset	= ax88179_reset,
-// #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 32)
-// 	.stop	= ax88179_stop,
-// 	.flags	= FLAG_ETHER | FLAG_FRAMING_AX | FLAG_AVOID_UNLINK_URBS,
-// #else
-// 	.flags	= FLAG_ETHER | FLAG_FRAMING_AX,
-// #endif
-// 	.rx_fixup = ax88179_rx_fixup,
-// 	.tx_fixup = ax88179_tx_fixup,
-// };
+impl KernelResultExt for DriverResult<()> {
+    fn from_kernel_errno(errno: c_int) -> Self {
+        KernelResult::from_kernel_errno(errno).map_err(DriverError::from)
+    }
 
-// static const struct driver_info sitecom_info = {
-// 	.description = "Sitecom USB 3.0 to Gigabit Adapter",
-// 	.bind	= ax88179_bind,
-// 	.unbind	= ax88179_unbind,
-// 	.status	= ax88179_status,
-// 	.link_reset = ax88179_link_reset,
-// 	.reset	= ax88179_reset,
-// #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 32)
-// 	.stop	= ax88179_stop,
-// 	.flags	= FLAG_ETHER | FLAG_FRAMING_AX | FLAG_AVOID_UNLINK_URBS,
-// #else
-// 	.flags	= FLAG_ETHER | FLAG_FRAMING_AX,
-// #endif
-// 	.rx_fixup = ax88179_rx_fixup,
-// 	.tx_fixup = ax88179_tx_fixup,
-// };
+    fn into_kernel_errno(self) -> c_int {
+        match self {
+            Ok(()) => 0,
+            Err(e) => e.to_kernel_errno(),
+        }
+    }
+}
 
-// static const struct driver_info lenovo_info = {
-// 	.description = "ThinkPad OneLinkDock USB GigaLAN",
-// 	.bind	= ax88179_bind,
-// 	.unbind	= ax88179_unbind,
-// 	.status	= ax88179_status,
-// 	.link_reset = ax88179_link_reset,
-// 	.reset	= ax88179_reset,
-// #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 32)
-// 	.stop	= ax88179_stop,
-// 	.flags	= FLAG_ETHER | FLAG_FRAMING_AX | FLAG_AVOID_UNLINK_URBS,
-// #else
-// 	.flags	= FLAG_ETHER | FLAG_FRAMING_AX,
-// #endif
-// 	.rx_fixup = ax88179_rx_fixup,
-// 	.tx_fixup = ax88179_tx_fixup,
-// };
+// All-zero table, `Default::default()` can't be used in a static initialiser
+const USB_DEVICE_ID_ZEROED: usb_device_id = unsafe { transmute([0u8; size_of::<usb_device_id>()]) };
+
+/// Declares the devices served by this driver. Each entry gets its own `driver_info`
+/// static, and a line in the exported `__mod_usb__products_device_table`.
+macro_rules! ax88179_devices {
+    ($(
+        $(#[doc = $doc:literal])*
+        $info:ident: ($vendor:literal, $product:literal) => $description:literal;
+    )*) => {
+        $(
+            $(#[doc = $doc])*
+            #[allow(non_upper_case_globals)]
+            static $info: SyncStatic<driver_info> = SyncStatic(driver_info {
+                description: concat!($description, "\0").as_ptr() as _,
+                flags: (FLAG_ETHER | FLAG_FRAMING_AX | FLAG_AVOID_UNLINK_URBS) as _,
+                manage_power: Some(ax88179_manage_power),
+                ..MiniDriver::<Ax88179>::DRIVER_INFO
+            });
+        )*
+
+        const AX88179_DEVICE_COUNT: usize = [$($vendor),*].len();
+
+        // modpost reads the ids straight out of this symbol to generate the module
+        // aliases. driver_info pointers can't be stored in a static initialiser, so
+        // they're left zero here and filled in by ax88179_id_table.
+        #[export_name = "__mod_usb__products_device_table"]
+        static PRODUCTS: [usb_device_id; AX88179_DEVICE_COUNT + 1] = [
+            $(
+                usb_device_id {
+                    match_flags: USB_DEVICE_ID_MATCH_DEVICE as _,
+                    idVendor: $vendor,
+                    idProduct: $product,
+                    ..USB_DEVICE_ID_ZEROED
+                },
+            )*
+            // End sentinel
+            USB_DEVICE_ID_ZEROED,
+        ];
 
-// static const struct driver_info toshiba_info = {
-// 	.description = "Toshiba USB 3.0 to Gigabit LAN Adapter",
-// 	.bind	= ax88179_bind,
-// 	.unbind	= ax88179_unbind,
-// 	.status	= ax88179_status,
-// 	.link_reset = ax88179_link_reset,
-// 	.reset	= ax88179_reset,
-// #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 32)
-// 	.stop	= ax88179_stop,
-// 	.flags	= FLAG_ETHER | FLAG_FRAMING_AX | FLAG_AVOID_UNLINK_URBS,
-// #else
-// 	.flags	= FLAG_ETHER | FLAG_FRAMING_AX,
-// #endif
-// 	.rx_fixup = ax88179_rx_fixup,
-// 	.tx_fixup = ax88179_tx_fixup,
-// };
+        // The driver_info for each entry of PRODUCTS, in the same order
+        static PRODUCT_INFO: [&SyncStatic<driver_info>; AX88179_DEVICE_COUNT] = [$(&$info),*];
+    };
+}
 
-// static const struct driver_info samsung_info = {
-// 	.description = "Samsung USB Ethernet Adapter",
-// 	.bind	= ax88179_bind,
-// 	.unbind = ax88179_unbind,
-// 	.status = ax88179_status,
-// 	.link_reset = ax88179_link_reset,
-// 	.reset	= ax88179_reset,
-// #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 32)
-// 	.stop	= ax88179_stop,
-// 	.flags	= FLAG_ETHER | FLAG_FRAMING_AX | FLAG_AVOID_UNLINK_URBS,
-// #else
-// 	.flags	= FLAG_ETHER | FLAG_FRAMING_AX,
-// #endif
-// 	.rx_fixup = ax88179_rx_fixup,
-// 	.tx_fixup = ax88179_tx_fixup,
-// };
+ax88179_devices! {
+    /// ASIX AX88179 10/100/1000
+    ax88179_info: (0x0b95, 0x1790) => "ASIX AX88179 USB 3.0 Gigabit Ethernet";
+    /// ASIX AX88178A 10/100/1000
+    ax88178a_info: (0x0b95, 0x178a) => "ASIX AX88178A USB 2.0 Gigabit Ethernet";
+    /// Sitecom USB 3.0 to Gigabit Adapter
+    sitecom_info: (0x0df6, 0x0072) => "Sitecom USB 3.0 to Gigabit Adapter";
+    /// ThinkPad OneLinkDock USB GigaLAN
+    lenovo_info: (0x17ef, 0x304b) => "ThinkPad OneLinkDock USB GigaLAN";
+    /// Toshiba USB3.0 to Gigabit LAN Adapter
+    toshiba_info: (0x0930, 0x0a13) => "Toshiba USB 3.0 to Gigabit LAN Adapter";
+    /// Samsung USB Ethernet Adapter
+    samsung_info: (0x04e8, 0xa100) => "Samsung USB Ethernet Adapter";
+    /// D-Link DUB-13x2 Ethernet Adapter
+    dlink_info: (0x2001, 0x4a00) => "DUB-1312/1332 USB3.0 to Gigabit Ethernet Adapter";
+    /// MCT USB 3.0 to Gigabit Ethernet Adapter
+    mct_info: (0x0711, 0x0179) => "USB 3.0 to Gigabit Ethernet Adapter";
+}
 
-// static const struct driver_info dlink_info = {
-// 	.description = "DUB-1312/1332 USB3.0 to Gigabit Ethernet Adapter",
-// 	.bind	= ax88179_bind,
-// 	.unbind = ax88179_unbind,
-// 	.status = ax88179_status,
-// 	.link_reset = ax88179_link_reset,
-// 	.reset	= ax88179_reset,
-// #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 32)
-// 	.stop	= ax88179_stop,
-// 	.flags	= FLAG_ETHER | FLAG_FRAMING_AX | FLAG_AVOID_UNLINK_URBS,
-// #else
-// 	.flags	= FLAG_ETHER | FLAG_FRAMING_AX,
-// #endif
-// 	.rx_fixup = ax88179_rx_fixup,
-// 	.tx_fixup = ax88179_tx_fixup,
-// };
+/// The id table handed to the USB core: PRODUCTS with the driver_info of each entry
+/// filled in.
+fn ax88179_id_table() -> Box<[usb_device_id]> {
+    let mut table: Box<[usb_device_id]> = Box::new(PRODUCTS);
 
-// static const struct driver_info mct_info = {
-// 	.description = "USB 3.0 to Gigabit Ethernet Adapter",
-// 	.bind   = ax88179_bind,
-// 	.unbind = ax88179_unbind,
-// 	.status = ax88179_status,
-// 	.link_reset = ax88179_link_reset,
-// 	.reset  = ax88179_reset,
-// #if LINUX_VERSION_CODE >= KERNEL_VERSION(2, 6, 32)
-// 	.stop   = ax88179_stop,
-// 	.flags  = FLAG_ETHER | FLAG_FRAMING_AX | FLAG_AVOID_UNLINK_URBS,
-// #else
-// 	.flags  = FLAG_ETHER | FLAG_FRAMING_AX,
-// #endif
-// 	.rx_fixup = ax88179_rx_fixup,
-// 	.tx_fixup = ax88179_tx_fixup,
-// };
+    for (id, info) in table.iter_mut().zip(PRODUCT_INFO.iter()) {
+        id.driver_info = &info.0 as *const driver_info as _;
+    }
 
-#[export_name = "__mod_usb__products_device_table"]
-static mut PRODUCTS: MaybeUninit<[usb_device_id; 2]> = MaybeUninit::uninit();
+    table
+}
 
 fn get_driver_info() -> usb_driver {
-    // TODO: wrap all this static_mut initialisation in a call_once
-    unsafe {
-        ax88179_info.as_mut_ptr().write(driver_info {
-            description: "ASIX AX88179 USB 3.0 Gigabit Ethernet\0".as_ptr() as _,
-            bind: Some(ax88179_bind),
-            unbind: Some(ax88179_unbind),
-            status: Some(ax88179_status),
-            link_reset: Some(ax88179_link_reset),
-            reset: Some(ax88179_reset),
-            stop: Some(ax88179_stop),
-            flags: (FLAG_ETHER | FLAG_FRAMING_AX | FLAG_AVOID_UNLINK_URBS) as _,
-            rx_fixup: Some(ax88179_rx_fixup),
-            tx_fixup: Some(ax88179_tx_fixup),
-            ..Default::default()
-        });
-
-        PRODUCTS.as_mut_ptr().write([
-            // ASIX AX88179 10/100/1000
-            usb_device_id {
-                match_flags: USB_DEVICE_ID_MATCH_DEVICE as _,
-                idVendor: 0x0b95,
-                idProduct: 0x1790,
-                driver_info: ax88179_info.as_ptr() as _,
-                ..Default::default()
-            },
-            // {
-            // 	/* ASIX AX88179 10/100/1000 */
-            // 	USB_DEVICE(0x0b95, 0x1790),
-            // 	.driver_info = (unsigned long) &ax88179_info,
-            // }, {
-            // 	/* ASIX AX88178A 10/100/1000 */
-            // 	USB_DEVICE(0x0b95, 0x178a),
-            // 	.driver_info = (unsigned long) &ax88178a_info,
-            // }, {
-            // 	/* Sitecom USB 3.0 to Gigabit Adapter */
-            // 	USB_DEVICE(0x0df6, 0x0072),
-            // 	.driver_info = (unsigned long) &sitecom_info,
-            // }, {
-            // 	/* ThinkPad OneLinkDock USB GigaLAN */
-            // 	USB_DEVICE(0x17ef, 0x304b),
-            // 	.driver_info = (unsigned long) &lenovo_info,
-            // }, {
-            // 	/* Toshiba USB3.0 to Gigabit LAN Adapter */
-            // 	USB_DEVICE(0x0930, 0x0a13),
-            // 	.driver_info = (unsigned long) &toshiba_info,
-            // }, {
-            // 	/* Samsung USB Ethernet Adapter */
-            // 	USB_DEVICE(0x04e8, 0xa100),
-            // 	.driver_info = (unsigned long) &samsung_info,
-            // }, {
-            // 	/* D-Link DUB-13x2 Ethernet Adapter */
-            // 	USB_DEVICE(0x2001, 0x4a00),
-            // 	.driver_info = (unsigned long) &dlink_info,
-            // }, {
-            // 	/* MCT USB 3.0 to Gigabit Ethernet Adapter */
-            // 	USB_DEVICE(0x0711, 0x0179),
-            // 	.driver_info = (unsigned long) &mct_info,
-            // },
-
-            // End sentinel
-            core::mem::zeroed(),
-        ]);
-    }
-
-    usb_driver {
+    let mut driver = usb_driver {
         name: "ax88179_178a\0".as_ptr() as _,
-        id_table: unsafe { &*PRODUCTS.as_ptr() }.as_ptr(),
         probe: Some(usbnet_probe),
         suspend: Some(ax88179_suspend),
         resume: Some(ax88179_resume),
+        reset_resume: Some(ax88179_reset_resume),
+        pre_reset: Some(ax88179_pre_reset),
+        post_reset: Some(ax88179_post_reset),
         disconnect: Some(usbnet_disconnect),
         ..Default::default()
-    }
+    };
+    // Only while the link is down, see ax88179_suspend
+    driver.set_supports_autosuspend(1);
+    driver
 }
 
 linux_kernel_module::kernel_module!(
@@ -2815,3 +3088,55 @@ linux_kernel_module::kernel_module!(
     description: DRIVER_DESCRIPTION,
     license: DRIVER_LICENSE
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tx_headers_carry_length_and_mss() {
+        assert_eq!(ax88179_tx_headers(60, 0, 512), (60, 0));
+        assert_eq!(ax88179_tx_headers(9000, 1448, 512), (9000, 1448));
+    }
+
+    #[test]
+    fn tx_headers_pad_exact_multiples_of_maxpacket() {
+        // With the 8 byte header, 504 and 1016 bytes end exactly on a packet boundary
+        assert_eq!(ax88179_tx_headers(504, 0, 512), (504, 0x80008000));
+        assert_eq!(
+            ax88179_tx_headers(1016, 1448, 512),
+            (1016, 0x80008000 | 1448)
+        );
+        assert_eq!(ax88179_tx_headers(1016, 0, 1024), (1016, 0x80008000));
+        assert_eq!(ax88179_tx_headers(505, 0, 512), (505, 0));
+        assert_eq!(ax88179_tx_headers(504, 0, 64), (504, 0x80008000));
+    }
+
+    #[test]
+    fn tx_headers_without_maxpacket_never_pad() {
+        assert_eq!(ax88179_tx_headers(504, 0, 0), (504, 0));
+    }
+
+    #[test]
+    fn tx_linearizes_only_paged_sg_skbs_without_dma_sg() {
+        // (can_dma_sg, sg_enabled, nonlinear) => needs linearize
+        let cases = [
+            ((false, false, false), false),
+            ((false, false, true), false),
+            ((false, true, false), false),
+            ((false, true, true), true),
+            ((true, false, false), false),
+            ((true, false, true), false),
+            ((true, true, false), false),
+            ((true, true, true), false),
+        ];
+
+        for &((can_dma_sg, sg_enabled, nonlinear), expected) in &cases {
+            assert_eq!(
+                ax88179_tx_needs_linearize(can_dma_sg, sg_enabled, nonlinear),
+                expected,
+                "can_dma_sg {can_dma_sg}, sg_enabled {sg_enabled}, nonlinear {nonlinear}"
+            );
+        }
+    }
+}
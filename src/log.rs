@@ -0,0 +1,164 @@
+/*
+ * Logging, after pr_err, netdev_err, netif_err and friends.
+ *
+ * Messages go out at a proper kernel log level. Per-device ones are prefixed like the
+ * kernel's own netdev_printk: driver, USB interface, and the interface name once it
+ * has one. The netif_* macros only log message classes enabled in the device's
+ * msg_enable, which is set with the msg_enable module parameter or
+ * `ethtool -s <iface> msglvl`. The pr_* ones are for what isn't about any one
+ * device, such as the module parameters, and only carry the module name.
+ */
+
+use core::fmt::{self, Write};
+
+use linux_kernel_module::bindings::{net_ratelimit, printk};
+use linux_kernel_module::c_types::c_int;
+
+use crate::net::UsbNet;
+
+/// A kernel log level, as the printk format string which carries it. KERN_* are
+/// macros.
+pub(crate) struct Level(&'static [u8]);
+
+pub(crate) const KERN_ERR: Level = Level(b"\x013%.*s\n\0");
+pub(crate) const KERN_WARNING: Level = Level(b"\x014%.*s\n\0");
+pub(crate) const KERN_INFO: Level = Level(b"\x016%.*s\n\0");
+pub(crate) const KERN_DEBUG: Level = Level(b"\x017%.*s\n\0");
+
+// msg_enable classes, from enum in linux/netdevice.h
+pub(crate) const NETIF_MSG_PROBE: u32 = 0x0002;
+pub(crate) const NETIF_MSG_LINK: u32 = 0x0004;
+pub(crate) const NETIF_MSG_IFUP: u32 = 0x0020;
+pub(crate) const NETIF_MSG_RX_ERR: u32 = 0x0040;
+pub(crate) const NETIF_MSG_TX_ERR: u32 = 0x0080;
+pub(crate) const NETIF_MSG_HW: u32 = 0x2000;
+
+// Anything longer is cut short
+const LINE_LEN: usize = 256;
+
+// pr_fmt's KBUILD_MODNAME prefix
+const MODULE_NAME: &str = "ax88179_178a";
+
+struct LineWriter {
+    buf: [u8; LINE_LEN],
+    len: usize,
+}
+
+impl LineWriter {
+    fn new() -> Self {
+        Self {
+            buf: [0; LINE_LEN],
+            len: 0,
+        }
+    }
+
+    fn print(&self, level: Level) {
+        unsafe { printk(level.0.as_ptr() as _, self.len as c_int, self.buf.as_ptr()) };
+    }
+}
+
+impl Write for LineWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = s.len().min(LINE_LEN - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+
+        if n < s.len() {
+            return Err(fmt::Error);
+        }
+
+        Ok(())
+    }
+}
+
+/// Logs a line which isn't about any one device, see pr_err! and friends.
+pub(crate) fn pr_printk(level: Level, args: fmt::Arguments<'_>) {
+    let mut line = LineWriter::new();
+
+    let _ = write!(line, "{MODULE_NAME}: {args}");
+    line.print(level);
+}
+
+/// Logs a line against `dev`, see netdev_err! and friends.
+pub(crate) fn netdev_printk(level: Level, dev: &UsbNet, args: fmt::Arguments<'_>) {
+    let mut line = LineWriter::new();
+    let (driver, bus, net) = (dev.driver_name(), dev.bus_name(), dev.net());
+
+    // Until usbnet registers the netdev its name is still a template, which
+    // netdev_printk leaves out too
+    let _ = if net.name().contains('%') {
+        write!(line, "{driver} {bus}: {args}")
+    } else {
+        write!(line, "{driver} {bus} {}: {args}", net.name())
+    };
+
+    line.print(level);
+}
+
+/// Whether a rate-limited message may go out now, shared with the networking core.
+pub(crate) fn ratelimit() -> bool {
+    unsafe { net_ratelimit() != 0 }
+}
+
+/// pr_err: logs `format!` style arguments, prefixed with the module name.
+macro_rules! pr_err {
+    ($($arg:tt)*) => {
+        $crate::log::pr_printk($crate::log::KERN_ERR, format_args!($($arg)*))
+    };
+}
+
+macro_rules! pr_info {
+    ($($arg:tt)*) => {
+        $crate::log::pr_printk($crate::log::KERN_INFO, format_args!($($arg)*))
+    };
+}
+
+macro_rules! netdev_log {
+    ($level:ident, $dev:expr, $($arg:tt)*) => {{
+        let dev: &$crate::net::UsbNet = &$dev;
+        $crate::log::netdev_printk($crate::log::$level, dev, format_args!($($arg)*))
+    }};
+}
+
+/// netdev_err: logs `format!` style arguments against a usbnet device.
+macro_rules! netdev_err {
+    ($dev:expr, $($arg:tt)*) => { netdev_log!(KERN_ERR, $dev, $($arg)*) };
+}
+
+macro_rules! netdev_warn {
+    ($dev:expr, $($arg:tt)*) => { netdev_log!(KERN_WARNING, $dev, $($arg)*) };
+}
+
+macro_rules! netdev_info {
+    ($dev:expr, $($arg:tt)*) => { netdev_log!(KERN_INFO, $dev, $($arg)*) };
+}
+
+macro_rules! netif_log {
+    ($level:ident, $dev:expr, $msg:ident, $($arg:tt)*) => {{
+        let dev: &$crate::net::UsbNet = &$dev;
+        if dev.msg_enabled($crate::log::$msg) {
+            netdev_log!($level, *dev, $($arg)*)
+        }
+    }};
+}
+
+/// netif_info: as netdev_info!, if the device's msg_enable has the NETIF_MSG_* class
+/// `$msg` set.
+macro_rules! netif_info {
+    ($dev:expr, $msg:ident, $($arg:tt)*) => { netif_log!(KERN_INFO, $dev, $msg, $($arg)*) };
+}
+
+macro_rules! netif_dbg {
+    ($dev:expr, $msg:ident, $($arg:tt)*) => { netif_log!(KERN_DEBUG, $dev, $msg, $($arg)*) };
+}
+
+/// An error in the NETIF_MSG_* class `$msg`, dropped beyond net_ratelimit's budget.
+/// For the RX and TX paths, which would otherwise flood the log.
+macro_rules! netif_err_ratelimited {
+    ($dev:expr, $msg:ident, $($arg:tt)*) => {{
+        let dev: &$crate::net::UsbNet = &$dev;
+        if dev.msg_enabled($crate::log::$msg) && $crate::log::ratelimit() {
+            netdev_log!(KERN_ERR, *dev, $($arg)*)
+        }
+    }};
+}
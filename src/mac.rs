@@ -0,0 +1,199 @@
+/*
+ * MAC address checks for ax88179_178a.
+ *
+ * Unprogrammed and badly programmed adapters report either garbage or one of ASIX's
+ * factory default addresses, which several adapters on one network then share.
+ * The address is taken from the first source holding a valid one, or failing
+ * that is random.
+ */
+
+#![forbid(unsafe_code)]
+
+use core::fmt;
+
+use crate::error::DriverError;
+
+pub(crate) const ETH_ALEN: usize = 6;
+
+/// ASIX Electronics' OUI
+const ASIX_OUI: [u8; 3] = [0x00, 0x0e, 0xc6];
+
+const AX88179_DEFAULT_MAC: [u8; ETH_ALEN] = [0x00, 0x0e, 0xc6, 0x81, 0x79, 0x01];
+const AX88178A_DEFAULT_MAC: [u8; ETH_ALEN] = [0x00, 0x0e, 0xc6, 0x81, 0x78, 0x01];
+
+/// Where an adapter's MAC address can come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MacSource {
+    /// The `local-mac-address` firmware / device tree property.
+    Firmware,
+    /// The node ID in a valid eFuse.
+    Efuse,
+    /// Words 0-2 of a valid EEPROM.
+    Eeprom,
+    /// Whatever the AX_NODE_ID register held at bind. Without an EEPROM, this is
+    /// where anything the chip loaded at power up ends up.
+    NodeId,
+    /// Generated, none of the others held a valid address.
+    Random,
+}
+
+impl MacSource {
+    /// Sources tried in order, before falling back to a random address.
+    pub(crate) const CHAIN: [MacSource; 4] = [
+        MacSource::Firmware,
+        MacSource::Efuse,
+        MacSource::Eeprom,
+        MacSource::NodeId,
+    ];
+}
+
+impl fmt::Display for MacSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MacSource::Firmware => "firmware",
+            MacSource::Efuse => "eFuse",
+            MacSource::Eeprom => "EEPROM",
+            MacSource::NodeId => "node ID register",
+            MacSource::Random => "random",
+        })
+    }
+}
+
+/// Why an address read from the adapter can't be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum InvalidMac {
+    /// All zero, or at least with an all zero OUI.
+    Zero,
+    /// Multicast / broadcast, the group bit is set.
+    Multicast,
+    /// One of ASIX's factory defaults, shared by every unprogrammed adapter.
+    VendorDefault,
+}
+
+impl fmt::Display for InvalidMac {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InvalidMac::Zero => "zero address",
+            InvalidMac::Multicast => "multicast address",
+            InvalidMac::VendorDefault => "vendor default address",
+        })
+    }
+}
+
+/// Checks an address, mirroring is_valid_ether_addr plus the vendor defaults.
+pub(crate) fn check_mac(addr: &[u8; ETH_ALEN]) -> Result<(), InvalidMac> {
+    if addr[..3] == [0, 0, 0] {
+        Err(InvalidMac::Zero)
+    } else if addr[0] & 0x01 != 0 {
+        Err(InvalidMac::Multicast)
+    } else if *addr == AX88179_DEFAULT_MAC || *addr == AX88178A_DEFAULT_MAC {
+        Err(InvalidMac::VendorDefault)
+    } else {
+        Ok(())
+    }
+}
+
+/// Builds a fallback address from three random bytes. It keeps the ASIX OUI so the
+/// adapter is still recognisable, with the locally administered bit set since the
+/// address wasn't assigned by ASIX.
+pub(crate) fn random_mac(random: [u8; 3]) -> [u8; ETH_ALEN] {
+    [
+        ASIX_OUI[0] | 0x02,
+        ASIX_OUI[1],
+        ASIX_OUI[2],
+        random[0],
+        random[1],
+        random[2],
+    ]
+}
+
+/// Why a generated address couldn't be saved to the EEPROM.
+pub(crate) enum PersistMacError {
+    /// Writing the EEPROM, or reading the address back, failed.
+    Io(DriverError),
+    /// The address read back isn't the one written, the adapter has no EEPROM or an
+    /// unsupported part.
+    Mismatch {
+        written: [u8; ETH_ALEN],
+        read: [u8; ETH_ALEN],
+    },
+}
+
+impl From<DriverError> for PersistMacError {
+    fn from(e: DriverError) -> Self {
+        PersistMacError::Io(e)
+    }
+}
+
+impl fmt::Display for PersistMacError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistMacError::Io(e) => write!(f, "{e}"),
+            PersistMacError::Mismatch { written, read } => write!(
+                f,
+                "invalid EEPROM part or non-EEPROM, wrote {} but read back {}",
+                MacAddr(written),
+                MacAddr(read)
+            ),
+        }
+    }
+}
+
+/// Formats an address as `aa:bb:cc:dd:ee:ff`.
+pub(crate) struct MacAddr<'a>(pub(crate) &'a [u8; ETH_ALEN]);
+
+impl fmt::Display for MacAddr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let a = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            a[0], a[1], a[2], a[3], a[4], a[5]
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_unusable_addresses() {
+        assert_eq!(check_mac(&[0; ETH_ALEN]), Err(InvalidMac::Zero));
+        assert_eq!(
+            check_mac(&[0x00, 0x00, 0x00, 0x12, 0x34, 0x56]),
+            Err(InvalidMac::Zero)
+        );
+        assert_eq!(check_mac(&[0xff; ETH_ALEN]), Err(InvalidMac::Multicast));
+        assert_eq!(
+            check_mac(&[0x01, 0x00, 0x5e, 0x00, 0x00, 0x01]),
+            Err(InvalidMac::Multicast)
+        );
+        assert_eq!(
+            check_mac(&AX88179_DEFAULT_MAC),
+            Err(InvalidMac::VendorDefault)
+        );
+        assert_eq!(
+            check_mac(&AX88178A_DEFAULT_MAC),
+            Err(InvalidMac::VendorDefault)
+        );
+    }
+
+    #[test]
+    fn accepts_unicast_addresses() {
+        assert_eq!(check_mac(&[0x00, 0x0e, 0xc6, 0x12, 0x34, 0x56]), Ok(()));
+        assert_eq!(check_mac(&[0x02, 0x0e, 0xc6, 0x81, 0x79, 0x01]), Ok(()));
+    }
+
+    #[test]
+    fn random_addresses_are_local_unicast_asix() {
+        let addr = random_mac([0x12, 0x34, 0x56]);
+        assert_eq!(addr, [0x02, 0x0e, 0xc6, 0x12, 0x34, 0x56]);
+        assert_eq!(check_mac(&addr), Ok(()));
+
+        // Even random bytes which would be invalid on their own
+        let addr = random_mac([0xff; 3]);
+        assert_eq!(addr[..3], [0x02, 0x0e, 0xc6]);
+        assert_eq!(check_mac(&addr), Ok(()));
+    }
+}
@@ -0,0 +1,164 @@
+/*
+ * Scaffolding for usbnet minidrivers.
+ *
+ * usbnet drives an adapter through the hooks in its driver_info. A minidriver
+ * implements UsbNetDriver instead, and MiniDriver::<D>::DRIVER_INFO fills those hooks
+ * with trampolines which wrap the raw pointers and look after the per-device state.
+ * Nothing in here is specific to the AX88179, other ASIX parts can use it as is.
+ */
+
+use alloc::boxed::Box;
+use core::marker::PhantomData;
+use core::mem::{size_of, transmute, ManuallyDrop};
+use core::slice;
+
+use linux_kernel_module::bindings::{driver_info, gfp_t, sk_buff, urb, usb_interface, usbnet};
+use linux_kernel_module::c_types::{c_int, c_ulong};
+
+use crate::error::{DriverResult, DriverResultExt};
+use crate::net::{SkBuff, UsbInterface, UsbNet};
+
+// All-zero table, `Default::default()` can't be used in a static initialiser
+const DRIVER_INFO_ZEROED: driver_info = unsafe { transmute([0u8; size_of::<driver_info>()]) };
+
+/// A usbnet minidriver. `Self` is the per-device state, made by bind and kept in
+/// usbnet.data[0] until unbind.
+///
+/// usbnet serializes bind, unbind, link_reset, reset and stop. status, rx_fixup and
+/// tx_fixup run in atomic context, alongside those and each other. The state is only
+/// ever shared between bind and unbind, so whatever changes in it needs interior
+/// mutability.
+pub(crate) trait UsbNetDriver: Sized {
+    /// Sets up a newly probed adapter, before usbnet registers its netdev.
+    fn bind(dev: &UsbNet, intf: &UsbInterface) -> DriverResult<Box<Self>>;
+
+    /// Tears down an adapter which is going away, the state is freed on return.
+    fn unbind(self: Box<Self>, dev: &UsbNet, intf: &UsbInterface);
+
+    /// Handles the data of a completed interrupt URB.
+    fn status(&self, dev: &UsbNet, event: &[u8]);
+
+    /// Reconfigures the adapter for a link which has come up.
+    fn link_reset(&self, dev: &UsbNet) -> DriverResult<()>;
+
+    /// Readies the adapter as the interface is brought up.
+    fn reset(&self, dev: &UsbNet) -> DriverResult<()>;
+
+    /// Quiesces the adapter as the interface is brought down.
+    fn stop(&self, dev: &UsbNet) -> DriverResult<()>;
+
+    /// Unframes a bulk-in transfer. usbnet keeps `skb`, passing it up the stack on Ok
+    /// and counting an RX error otherwise. Any further frames it held go up through
+    /// `UsbNet::skb_return`.
+    fn rx_fixup(&self, dev: &UsbNet, skb: &mut SkBuff) -> DriverResult<()>;
+
+    /// Frames an outgoing skb, returning the one to transmit. Dropping it drops the frame.
+    fn tx_fixup(&self, dev: &UsbNet, skb: SkBuff, flags: gfp_t) -> DriverResult<SkBuff>;
+}
+
+/// The usbnet side of a `D`.
+pub(crate) struct MiniDriver<D>(PhantomData<D>);
+
+impl<D: UsbNetDriver> MiniDriver<D> {
+    /// A driver_info with every hook routed to `D`. Each device table entry adds its
+    /// description and flags on top.
+    pub(crate) const DRIVER_INFO: driver_info = driver_info {
+        bind: Some(Self::bind),
+        unbind: Some(Self::unbind),
+        status: Some(Self::status),
+        link_reset: Some(Self::link_reset),
+        reset: Some(Self::reset),
+        stop: Some(Self::stop),
+        rx_fixup: Some(Self::rx_fixup),
+        tx_fixup: Some(Self::tx_fixup),
+        ..DRIVER_INFO_ZEROED
+    };
+
+    /// The state of a bound device.
+    ///
+    /// # Safety
+    /// `dev` must be bound to `D`'s driver_info, and the returned reference must not
+    /// outlive the binding. The hooks may be running alongside, as may anything else
+    /// reaching the state from outside them, e.g. ethtool.
+    pub(crate) unsafe fn data<'a>(dev: *mut usbnet) -> &'a D {
+        &*((*dev).data[0] as *const D)
+    }
+
+    unsafe extern "C" fn bind(dev: *mut usbnet, intf: *mut usb_interface) -> c_int {
+        let dev = UsbNet::from_raw(dev);
+
+        D::bind(&dev, &UsbInterface::from_raw(intf))
+            .map(|data| (*dev.as_ptr()).data[0] = Box::into_raw(data) as c_ulong)
+            .into_errno_logged(&dev, "bind")
+    }
+
+    unsafe extern "C" fn unbind(dev: *mut usbnet, intf: *mut usb_interface) {
+        let data = (*dev).data[0] as *mut D;
+
+        if !data.is_null() {
+            (*dev).data[0] = 0;
+            Box::from_raw(data).unbind(&UsbNet::from_raw(dev), &UsbInterface::from_raw(intf));
+        }
+    }
+
+    unsafe extern "C" fn status(dev: *mut usbnet, urb: *mut urb) {
+        let buf = (*urb).transfer_buffer as *const u8;
+        let event = if buf.is_null() {
+            &[][..]
+        } else {
+            slice::from_raw_parts(buf, (*urb).actual_length as usize)
+        };
+
+        Self::data(dev).status(&UsbNet::from_raw(dev), event)
+    }
+
+    unsafe extern "C" fn link_reset(dev: *mut usbnet) -> c_int {
+        let dev = UsbNet::from_raw(dev);
+
+        Self::data(dev.as_ptr())
+            .link_reset(&dev)
+            .into_errno_logged(&dev, "link_reset")
+    }
+
+    unsafe extern "C" fn reset(dev: *mut usbnet) -> c_int {
+        let dev = UsbNet::from_raw(dev);
+
+        Self::data(dev.as_ptr())
+            .reset(&dev)
+            .into_errno_logged(&dev, "reset")
+    }
+
+    unsafe extern "C" fn stop(dev: *mut usbnet) -> c_int {
+        let dev = UsbNet::from_raw(dev);
+
+        Self::data(dev.as_ptr())
+            .stop(&dev)
+            .into_errno_logged(&dev, "stop")
+    }
+
+    unsafe extern "C" fn rx_fixup(dev: *mut usbnet, skb: *mut sk_buff) -> c_int {
+        // usbnet frees or passes on the skb itself, whatever we return
+        let mut skb = ManuallyDrop::new(SkBuff::from_raw(skb));
+
+        Self::data(dev)
+            .rx_fixup(&UsbNet::from_raw(dev), &mut skb)
+            .is_ok() as c_int
+    }
+
+    unsafe extern "C" fn tx_fixup(
+        dev: *mut usbnet,
+        skb: *mut sk_buff,
+        flags: gfp_t,
+    ) -> *mut sk_buff {
+        let dev = UsbNet::from_raw(dev);
+
+        // A failed skb is ours to free, which dropping it has done
+        match Self::data(dev.as_ptr()).tx_fixup(&dev, SkBuff::from_raw(skb), flags) {
+            Ok(skb) => skb.into_raw(),
+            Err(e) => {
+                netif_err_ratelimited!(dev, NETIF_MSG_TX_ERR, "tx_fixup failed: {e}");
+                core::ptr::null_mut()
+            }
+        }
+    }
+}
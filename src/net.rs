@@ -0,0 +1,458 @@
+/*
+ * Safe wrappers over the usb_interface, usbnet, net_device and sk_buff structures.
+ *
+ * The kernel calls the driver with raw pointers. They are wrapped once, where they
+ * come in: in the trampolines minidriver.rs generates for the usbnet hooks, and in the
+ * extern "C" usb_driver, ethtool, MII and sysfs callbacks. The driver logic behind
+ * them can then be safe code. Several of the accessors stand in for static inline
+ * kernel helpers, which bindgen can't see.
+ */
+
+use core::mem::{forget, size_of};
+use core::ptr;
+use core::slice;
+
+use linux_kernel_module::bindings::{
+    __dev_kfree_skb_any, __pskb_pull_tail, dev_driver_string, ethtool_ops, gfp_t, mii_check_media,
+    mii_nway_restart, net_device, netdev_features_t, netdev_state_t___LINK_STATE_NOCARRIER,
+    netif_carrier_off, pm_message_t, pskb_expand_head, sk_buff, skb_clone,
+    skb_free_reason_SKB_REASON_DROPPED, skb_pull, skb_push, skb_shared_info, skb_trim,
+    usb_autopm_get_interface_async, usb_autopm_put_interface_async, usb_interface, usbnet,
+    usbnet_defer_kevent, usbnet_resume, usbnet_skb_return, usbnet_suspend, EINPROGRESS,
+};
+use linux_kernel_module::c_types::{c_char, c_int};
+use linux_kernel_module::{Error, KernelResult};
+
+use crate::mac::ETH_ALEN;
+use crate::KernelResultExt;
+
+/// A USB interface, as the usb_driver callbacks get it.
+#[derive(Clone, Copy)]
+pub(crate) struct UsbInterface(*mut usb_interface);
+
+impl UsbInterface {
+    /// # Safety
+    /// `intf` must be a valid interface which outlives the returned `UsbInterface`.
+    /// Its driver data, if set, must be the usbnet device usbnet_probe bound to it.
+    pub(crate) unsafe fn from_raw(intf: *mut usb_interface) -> Self {
+        Self(intf)
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut usb_interface {
+        self.0
+    }
+
+    /// The usbnet device bound to the interface, ENODEV if there's none.
+    /// usb_get_intfdata is static inline.
+    pub(crate) fn usbnet(&self) -> KernelResult<UsbNet> {
+        let dev = unsafe { (*self.0).dev.driver_data as *mut usbnet };
+        if dev.is_null() {
+            return Err(Error::ENODEV);
+        }
+
+        Ok(unsafe { UsbNet::from_raw(dev) })
+    }
+
+    /// Whether the device must be able to wake itself up to autosuspend.
+    pub(crate) fn set_needs_remote_wakeup(&self, on: bool) {
+        unsafe { (*self.0).set_needs_remote_wakeup(on as _) }
+    }
+
+    /// Stops usbnet's transfers for `message`, from suspend or pre_reset.
+    pub(crate) fn usbnet_suspend(&self, message: pm_message_t) -> KernelResult<()> {
+        KernelResult::from_kernel_errno(unsafe { usbnet_suspend(self.0, message) })
+    }
+
+    /// Restarts usbnet's transfers, undoing `usbnet_suspend`.
+    pub(crate) fn usbnet_resume(&self) -> KernelResult<()> {
+        KernelResult::from_kernel_errno(unsafe { usbnet_resume(self.0) })
+    }
+}
+
+/// A usbnet device.
+#[derive(Clone, Copy)]
+pub(crate) struct UsbNet(*mut usbnet);
+
+impl UsbNet {
+    /// # Safety
+    /// `dev` must be a bound usbnet device which outlives the returned `UsbNet`.
+    pub(crate) unsafe fn from_raw(dev: *mut usbnet) -> Self {
+        Self(dev)
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut usbnet {
+        self.0
+    }
+
+    pub(crate) fn net(&self) -> NetDevice {
+        unsafe { NetDevice::from_raw((*self.0).net) }
+    }
+
+    pub(crate) fn intf(&self) -> UsbInterface {
+        unsafe { UsbInterface::from_raw((*self.0).intf) }
+    }
+
+    pub(crate) fn maxpacket(&self) -> u32 {
+        unsafe { (*self.0).maxpacket as u32 }
+    }
+
+    pub(crate) fn can_dma_sg(&self) -> bool {
+        unsafe { (*self.0).can_dma_sg() != 0 }
+    }
+
+    pub(crate) fn set_can_dma_sg(&self, on: bool) {
+        unsafe { (*self.0).set_can_dma_sg(on as _) }
+    }
+
+    /// Whether the host controller can DMA scatter-gather lists with arbitrarily
+    /// sized elements. usb_device_no_sg_constraint is static inline.
+    pub(crate) fn no_sg_constraint(&self) -> bool {
+        unsafe {
+            let udev = (*self.0).udev;
+            if udev.is_null() || (*udev).bus.is_null() {
+                return false;
+            }
+
+            let bus = &*(*udev).bus;
+            bus.sg_tablesize != 0 && bus.no_sg_constraint() != 0
+        }
+    }
+
+    pub(crate) fn set_rx_urb_size(&self, size: usize) {
+        unsafe { (*self.0).rx_urb_size = size as _ }
+    }
+
+    /// Restarts autonegotiation. Goes through the MII mdio hooks, which take the PHY.
+    pub(crate) fn mii_nway_restart(&self) {
+        unsafe { mii_nway_restart(&mut (*self.0).mii) };
+    }
+
+    /// Updates the carrier from the PHY's link state, logging any change. Goes
+    /// through the MII mdio hooks, which take the PHY.
+    pub(crate) fn mii_check_media(&self) {
+        unsafe { mii_check_media(&mut (*self.0).mii, 1, 1) };
+    }
+
+    /// Has usbnet's kevent worker handle `event`, one of the EVENT_* flags.
+    pub(crate) fn defer_kevent(&self, event: u32) {
+        unsafe { usbnet_defer_kevent(self.0, event as c_int) }
+    }
+
+    /// Has the USB core queue another autosuspend attempt, as it otherwise only does
+    /// once usbnet's own activity stops. Doesn't sleep.
+    pub(crate) fn request_autosuspend(&self) {
+        unsafe {
+            let intf = self.intf().as_ptr();
+
+            // Dropping the reference taken here is what queues the attempt. A resume
+            // in progress still leaves the reference taken.
+            let ret = usb_autopm_get_interface_async(intf);
+            if ret >= 0 || ret == -(EINPROGRESS as c_int) {
+                usb_autopm_put_interface_async(intf);
+            }
+        }
+    }
+
+    /// Passes a received frame up the stack.
+    pub(crate) fn skb_return(&self, skb: SkBuff) {
+        unsafe { usbnet_skb_return(self.0, skb.into_raw()) }
+    }
+
+    /// Whether any of the NETIF_MSG_* classes in `msg` are enabled, netif_msg_* are
+    /// macros.
+    pub(crate) fn msg_enabled(&self, msg: u32) -> bool {
+        unsafe { (*self.0).msg_enable & msg != 0 }
+    }
+
+    /// The name of the driver bound to the USB interface.
+    pub(crate) fn driver_name(&self) -> &str {
+        unsafe { c_str(dev_driver_string(&(*(*self.0).intf).dev)) }
+    }
+
+    /// The USB interface's device name, e.g. "2-1:1.0". dev_name is static inline.
+    pub(crate) fn bus_name(&self) -> &str {
+        unsafe { c_str((*(*self.0).intf).dev.kobj.name) }
+    }
+}
+
+/// A NUL-terminated string owned by the kernel.
+unsafe fn c_str<'a>(s: *const c_char) -> &'a str {
+    if s.is_null() {
+        return "?";
+    }
+
+    let mut len = 0;
+    while *s.add(len) != 0 {
+        len += 1;
+    }
+
+    core::str::from_utf8(slice::from_raw_parts(s as *const u8, len)).unwrap_or("?")
+}
+
+// NETDEV_ALIGN from netdevice.h
+const NETDEV_ALIGN: usize = 32;
+
+/// A network interface.
+#[derive(Clone, Copy)]
+pub(crate) struct NetDevice(*mut net_device);
+
+impl NetDevice {
+    /// # Safety
+    /// `net` must be a registered or allocated net_device which outlives the
+    /// returned `NetDevice`.
+    pub(crate) unsafe fn from_raw(net: *mut net_device) -> Self {
+        Self(net)
+    }
+
+    /// The usbnet device kept in the private area, netdev_priv is static inline.
+    ///
+    /// # Safety
+    /// The net_device must have been allocated by usbnet_probe.
+    pub(crate) unsafe fn usbnet(&self) -> UsbNet {
+        let offset = (size_of::<net_device>() + NETDEV_ALIGN - 1) & !(NETDEV_ALIGN - 1);
+        UsbNet::from_raw((self.0 as *mut u8).add(offset) as *mut usbnet)
+    }
+
+    /// The interface name, still the "eth%d" template until usbnet registers it.
+    pub(crate) fn name(&self) -> &str {
+        let name = unsafe { &(*self.0).name };
+        let name = unsafe { slice::from_raw_parts(name.as_ptr() as *const u8, name.len()) };
+        let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+
+        core::str::from_utf8(&name[..len]).unwrap_or("?")
+    }
+
+    pub(crate) fn dev_addr(&self) -> [u8; ETH_ALEN] {
+        unsafe { *((*self.0).dev_addr as *const [u8; ETH_ALEN]) }
+    }
+
+    pub(crate) fn set_dev_addr(&self, addr: &[u8; ETH_ALEN]) {
+        unsafe { *((*self.0).dev_addr as *mut [u8; ETH_ALEN]) = *addr }
+    }
+
+    pub(crate) fn set_perm_addr(&self, addr: &[u8; ETH_ALEN]) {
+        unsafe { (*self.0).perm_addr[..ETH_ALEN].copy_from_slice(addr) }
+    }
+
+    /// One of the NET_ADDR_* values, saying where dev_addr came from.
+    pub(crate) fn set_addr_assign_type(&self, assign_type: u8) {
+        unsafe { (*self.0).addr_assign_type = assign_type }
+    }
+
+    pub(crate) fn mtu(&self) -> u32 {
+        unsafe { (*self.0).mtu }
+    }
+
+    pub(crate) fn features(&self) -> netdev_features_t {
+        unsafe { (*self.0).features }
+    }
+
+    pub(crate) fn set_features(&self, features: netdev_features_t) {
+        unsafe { (*self.0).features = features }
+    }
+
+    /// The features the user may toggle through ethtool -K.
+    pub(crate) fn hw_features(&self) -> netdev_features_t {
+        unsafe { (*self.0).hw_features }
+    }
+
+    pub(crate) fn set_hw_features(&self, features: netdev_features_t) {
+        unsafe { (*self.0).hw_features = features }
+    }
+
+    pub(crate) fn set_ethtool_ops(&self, ops: &'static ethtool_ops) {
+        unsafe { (*self.0).ethtool_ops = ops }
+    }
+
+    /// netif_carrier_ok is static inline
+    pub(crate) fn carrier_ok(&self) -> bool {
+        unsafe { (*self.0).state & (1 << netdev_state_t___LINK_STATE_NOCARRIER) == 0 }
+    }
+
+    pub(crate) fn carrier_off(&self) {
+        unsafe { netif_carrier_off(self.0) }
+    }
+}
+
+// NET_SKB_PAD is max(32, L1_CACHE_BYTES)
+const NET_SKB_PAD: u32 = 64;
+const SKB_DATAREF_SHIFT: u32 = 16;
+const SKB_DATAREF_MASK: c_int = (1 << SKB_DATAREF_SHIFT) - 1;
+
+// skb->ip_summed values
+pub(crate) const CHECKSUM_NONE: u8 = 0;
+pub(crate) const CHECKSUM_UNNECESSARY: u8 = 1;
+
+/// An owned socket buffer, freed when dropped unless handed back to the kernel
+/// through `into_raw`.
+pub(crate) struct SkBuff(*mut sk_buff);
+
+impl SkBuff {
+    /// # Safety
+    /// `skb` must be a valid sk_buff whose reference the caller owns.
+    pub(crate) unsafe fn from_raw(skb: *mut sk_buff) -> Self {
+        Self(skb)
+    }
+
+    /// Gives up ownership without freeing the skb.
+    pub(crate) fn into_raw(self) -> *mut sk_buff {
+        let skb = self.0;
+        forget(self);
+        skb
+    }
+
+    fn shinfo(&self) -> *mut skb_shared_info {
+        unsafe { (*self.0).head.offset((*self.0).end as isize) as _ }
+    }
+
+    pub(crate) fn len(&self) -> u32 {
+        unsafe { (*self.0).len }
+    }
+
+    pub(crate) fn is_nonlinear(&self) -> bool {
+        unsafe { (*self.0).data_len != 0 }
+    }
+
+    /// The linear part of the data.
+    pub(crate) fn data(&self) -> &[u8] {
+        unsafe {
+            let headlen = (*self.0).len - (*self.0).data_len;
+            slice::from_raw_parts((*self.0).data, headlen as usize)
+        }
+    }
+
+    pub(crate) fn headroom(&self) -> u32 {
+        unsafe { (*self.0).data.offset_from((*self.0).head) as u32 }
+    }
+
+    /// Free space after the linear data, none for a paged skb. skb_tailroom is
+    /// static inline.
+    pub(crate) fn tailroom(&self) -> u32 {
+        if self.is_nonlinear() {
+            return 0;
+        }
+
+        unsafe { (*self.0).end - (*self.0).tail }
+    }
+
+    pub(crate) fn gso_size(&self) -> u32 {
+        unsafe { (*self.shinfo()).gso_size as u32 }
+    }
+
+    pub(crate) fn set_ip_summed(&mut self, ip_summed: u8) {
+        unsafe { (*self.0).set_ip_summed(ip_summed) }
+    }
+
+    pub(crate) fn set_truesize(&mut self, truesize: u32) {
+        unsafe { (*self.0).truesize = truesize }
+    }
+
+    /// Cuts the data down to `len` bytes.
+    pub(crate) fn trim(&mut self, len: u32) -> KernelResult<()> {
+        if len > self.len() || self.is_nonlinear() {
+            return Err(Error::EINVAL);
+        }
+
+        unsafe { skb_trim(self.0, len) };
+        Ok(())
+    }
+
+    /// Drops `len` bytes from the start of the data.
+    pub(crate) fn pull(&mut self, len: u32) -> KernelResult<()> {
+        if unsafe { skb_pull(self.0, len) }.is_null() {
+            return Err(Error::EINVAL);
+        }
+
+        Ok(())
+    }
+
+    /// Prepends `len` bytes to the data, returning them to be filled in.
+    pub(crate) fn push(&mut self, len: u32) -> KernelResult<&mut [u8]> {
+        // skb_push panics rather than fail
+        if len > self.headroom() {
+            return Err(Error::ENOMEM);
+        }
+
+        let data = unsafe { skb_push(self.0, len) };
+        Ok(unsafe { slice::from_raw_parts_mut(data, len as usize) })
+    }
+
+    /// A new skb sharing this one's data.
+    pub(crate) fn clone(&self, flags: gfp_t) -> KernelResult<SkBuff> {
+        let skb = unsafe { skb_clone(self.0, flags) };
+
+        if skb.is_null() {
+            return Err(Error::ENOMEM);
+        }
+
+        Ok(SkBuff(skb))
+    }
+
+    /// Pulls any paged data into the linear area.
+    pub(crate) fn linearize(&mut self) -> KernelResult<()> {
+        if !self.is_nonlinear() {
+            return Ok(());
+        }
+
+        let data_len = unsafe { (*self.0).data_len };
+        if unsafe { __pskb_pull_tail(self.0, data_len as _) }.is_null() {
+            return Err(Error::ENOMEM);
+        }
+
+        Ok(())
+    }
+
+    fn header_cloned(&self) -> bool {
+        unsafe {
+            if (*self.0).cloned() == 0 {
+                return false;
+            }
+
+            let dataref = ptr::read_volatile(&(*self.shinfo()).dataref.counter);
+            (dataref & SKB_DATAREF_MASK) - (dataref >> SKB_DATAREF_SHIFT) != 1
+        }
+    }
+
+    /// Moves the linear data back to start `headroom` bytes into the buffer, giving
+    /// up tailroom for it. Only for a linear skb whose header nobody else shares.
+    pub(crate) fn move_data(&mut self, headroom: u32) -> KernelResult<()> {
+        if self.is_nonlinear()
+            || self.header_cloned()
+            || headroom > self.headroom() + self.tailroom()
+        {
+            return Err(Error::EINVAL);
+        }
+
+        unsafe {
+            let skb = &mut *self.0;
+            let data = skb.head.add(headroom as usize);
+
+            ptr::copy(skb.data, data, skb.len as usize);
+            skb.data = data;
+            // skb_set_tail_pointer is static inline
+            skb.tail = headroom + skb.len;
+        }
+
+        Ok(())
+    }
+
+    /// Makes sure `headroom` bytes can be pushed in front of the linear data without
+    /// touching anyone else's copy of the header. Paged fragments are left where they are.
+    pub(crate) fn cow_head(&mut self, headroom: u32, flags: gfp_t) -> KernelResult<()> {
+        let delta = headroom.saturating_sub(self.headroom());
+
+        if delta > 0 || self.header_cloned() {
+            let nhead = (delta + NET_SKB_PAD - 1) & !(NET_SKB_PAD - 1);
+            let ret = unsafe { pskb_expand_head(self.0, nhead as _, 0, flags) };
+            KernelResult::from_kernel_errno(ret)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for SkBuff {
+    fn drop(&mut self) {
+        unsafe { __dev_kfree_skb_any(self.0, skb_free_reason_SKB_REASON_DROPPED) }
+    }
+}
@@ -0,0 +1,277 @@
+/*
+ * Integrity checks for the AX88179's non-volatile configuration.
+ *
+ * The MAC address, LED mode and other settings come either from an external EEPROM
+ * or from the chip's 64 byte eFuse. Both carry a checksum, and a blank part reads
+ * back as 0xff. This only validates contents already read from the device, the reads
+ * themselves are done by ax88179_check_nvm.
+ */
+
+#![forbid(unsafe_code)]
+
+use core::fmt;
+
+use crate::mac::ETH_ALEN;
+
+/// Bytes of the EEPROM header read for validation, the first six 16 bit words.
+pub(crate) const EEPROM_HEADER_LEN: usize = 12;
+
+pub(crate) const EFUSE_LEN: usize = 64;
+
+// eFuse offset of the 6 byte node ID, after the marker in byte 0
+const EFUSE_NODE_ID: usize = 1;
+
+// eFuse offset of the 16 bit LED mode
+const EFUSE_LED_MODE: usize = 51;
+
+/// What an unprogrammed eFuse byte reads as.
+pub(crate) const EFUSE_BLANK: u8 = 0xff;
+
+/// Which store, if any, holds a trustworthy configuration.
+pub(crate) enum Nvm {
+    /// The EEPROM is present and its checksum matches.
+    Eeprom,
+    /// There is no valid EEPROM, but the eFuse is programmed and its checksum matches.
+    Efuse(Efuse),
+    /// Neither can be trusted, the driver falls back to its built in defaults.
+    Neither,
+}
+
+/// Validated eFuse contents.
+pub(crate) struct Efuse([u8; EFUSE_LEN]);
+
+impl Efuse {
+    /// Returns the eFuse contents if they are programmed and the checksum matches.
+    pub(crate) fn new(efuse: [u8; EFUSE_LEN]) -> Option<Self> {
+        if efuse_valid(&efuse) {
+            Some(Self(efuse))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn node_id(&self) -> [u8; ETH_ALEN] {
+        let mut addr = [0u8; ETH_ALEN];
+        addr.copy_from_slice(&self.0[EFUSE_NODE_ID..EFUSE_NODE_ID + ETH_ALEN]);
+        addr
+    }
+
+    pub(crate) fn led_mode(&self) -> u16 {
+        u16::from_le_bytes([self.0[EFUSE_LED_MODE], self.0[EFUSE_LED_MODE + 1]])
+    }
+}
+
+/// The EEPROM checksum, folded sum of bytes 6..=9 plus the checksum byte 10.
+/// Valid headers sum to 0xff.
+pub(crate) fn eeprom_checksum(header: &[u8; EEPROM_HEADER_LEN]) -> u16 {
+    let csum: u16 = header[6..10].iter().map(|&b| b as u16).sum();
+
+    (csum >> 8) + (csum & 0xff) + header[10] as u16
+}
+
+pub(crate) fn eeprom_valid(header: &[u8; EEPROM_HEADER_LEN]) -> bool {
+    header[0] != 0xff && eeprom_checksum(header) == 0xff
+}
+
+/// Sum of `bytes`, with carries folded back in until it fits a byte. A valid eFuse
+/// sums to 0xff.
+pub(crate) fn efuse_checksum(bytes: &[u8]) -> u8 {
+    let mut csum: u32 = bytes.iter().map(|&b| b as u32).sum();
+
+    while csum > 0xff {
+        csum = (csum & 0xff) + (csum >> 8);
+    }
+
+    csum as u8
+}
+
+pub(crate) fn efuse_valid(efuse: &[u8; EFUSE_LEN]) -> bool {
+    efuse[0] != EFUSE_BLANK && efuse_checksum(efuse) == 0xff
+}
+
+/// Why a requested eFuse write was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EfuseWriteError {
+    /// The eFuse already holds a valid configuration, it's only programmed once.
+    AlreadyProgrammed,
+    /// The write runs past the end of the eFuse.
+    OutOfRange,
+    /// The byte at this offset is already programmed.
+    NotBlank(usize),
+    /// The resulting eFuse contents wouldn't pass the checksum.
+    Checksum,
+}
+
+impl fmt::Display for EfuseWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EfuseWriteError::AlreadyProgrammed => f.write_str("eFuse is already programmed"),
+            EfuseWriteError::OutOfRange => f.write_str("write past the end of the eFuse"),
+            EfuseWriteError::NotBlank(offset) => {
+                write!(f, "eFuse byte {offset:#04x} is already programmed")
+            }
+            EfuseWriteError::Checksum => f.write_str("eFuse checksum would be invalid"),
+        }
+    }
+}
+
+/// Merges `data` at `offset` into the `current` eFuse contents, and returns the new
+/// contents if they can be burned. eFuse bits can't be cleared again, so every byte
+/// written must still be blank, and the result must be a complete, valid eFuse.
+pub(crate) fn plan_efuse_write(
+    current: &[u8; EFUSE_LEN],
+    offset: usize,
+    data: &[u8],
+) -> Result<[u8; EFUSE_LEN], EfuseWriteError> {
+    if efuse_valid(current) {
+        return Err(EfuseWriteError::AlreadyProgrammed);
+    }
+
+    let end = offset
+        .checked_add(data.len())
+        .filter(|&end| end <= EFUSE_LEN)
+        .ok_or(EfuseWriteError::OutOfRange)?;
+
+    let mut new = *current;
+    for (i, &b) in (offset..end).zip(data.iter()) {
+        if current[i] != EFUSE_BLANK {
+            return Err(EfuseWriteError::NotBlank(i));
+        }
+        new[i] = b;
+    }
+
+    if !efuse_valid(&new) {
+        return Err(EfuseWriteError::Checksum);
+    }
+
+    Ok(new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eeprom_header(sum_bytes: [u8; 4], checksum: u8) -> [u8; EEPROM_HEADER_LEN] {
+        let mut header = [0u8; EEPROM_HEADER_LEN];
+        header[6..10].copy_from_slice(&sum_bytes);
+        header[10] = checksum;
+        header
+    }
+
+    /// A programmed eFuse, 0x01 in the first byte and the checksum in the last.
+    fn efuse() -> [u8; EFUSE_LEN] {
+        let mut efuse = [0u8; EFUSE_LEN];
+        efuse[0] = 0x01;
+        efuse[EFUSE_LED_MODE] = 0x21;
+        efuse[EFUSE_LED_MODE + 1] = 0x9d;
+        efuse[EFUSE_LEN - 1] = 0xff - 0x01 - 0x21 - 0x9d;
+        efuse
+    }
+
+    #[test]
+    fn eeprom_checksum_folds_carries() {
+        assert_eq!(
+            eeprom_checksum(&eeprom_header([0x10, 0x20, 0x30, 0x40], 0x5f)),
+            0xff
+        );
+        // 0x3fc folds to 0x03 + 0xfc
+        assert_eq!(eeprom_checksum(&eeprom_header([0xff; 4], 0x00)), 0xff);
+    }
+
+    #[test]
+    fn eeprom_validity() {
+        assert!(eeprom_valid(&eeprom_header([0x10, 0x20, 0x30, 0x40], 0x5f)));
+        assert!(eeprom_valid(&eeprom_header([0xff; 4], 0x00)));
+
+        // Corrupt checksum byte, then corrupt data
+        assert!(!eeprom_valid(&eeprom_header(
+            [0x10, 0x20, 0x30, 0x40],
+            0x5e
+        )));
+        assert!(!eeprom_valid(&eeprom_header(
+            [0x10, 0x20, 0x30, 0x41],
+            0x5f
+        )));
+
+        // Blank
+        let mut header = eeprom_header([0x10, 0x20, 0x30, 0x40], 0x5f);
+        header[0] = 0xff;
+        assert!(!eeprom_valid(&header));
+    }
+
+    #[test]
+    fn efuse_checksum_folds_carries() {
+        assert_eq!(efuse_checksum(&[]), 0);
+        assert_eq!(efuse_checksum(&[0x80, 0x7f]), 0xff);
+        // 0x1fe folds to 0x01 + 0xfe
+        assert_eq!(efuse_checksum(&[0xff, 0xff]), 0xff);
+        // 0x2ff folds to 0x101, then 0x02
+        assert_eq!(efuse_checksum(&[0xff, 0xff, 0xff, 0x02]), 0x02);
+        assert_eq!(efuse_checksum(&efuse()), 0xff);
+    }
+
+    #[test]
+    fn efuse_validity() {
+        assert!(efuse_valid(&efuse()));
+
+        let mut corrupt = efuse();
+        corrupt[10] ^= 0x01;
+        assert!(!efuse_valid(&corrupt));
+
+        // A blank eFuse sums to 0xff too
+        let blank = [EFUSE_BLANK; EFUSE_LEN];
+        assert_eq!(efuse_checksum(&blank), 0xff);
+        assert!(!efuse_valid(&blank));
+    }
+
+    #[test]
+    fn efuse_write_plans() {
+        let blank = [EFUSE_BLANK; EFUSE_LEN];
+
+        assert_eq!(plan_efuse_write(&blank, 0, &efuse()), Ok(efuse()));
+        assert_eq!(
+            plan_efuse_write(&efuse(), 0, &efuse()),
+            Err(EfuseWriteError::AlreadyProgrammed)
+        );
+        assert_eq!(
+            plan_efuse_write(&blank, 1, &efuse()),
+            Err(EfuseWriteError::OutOfRange)
+        );
+        assert_eq!(
+            plan_efuse_write(&blank, usize::MAX, &[0]),
+            Err(EfuseWriteError::OutOfRange)
+        );
+
+        // Only the first byte written, so the checksum can't match
+        assert_eq!(
+            plan_efuse_write(&blank, 0, &[0x01]),
+            Err(EfuseWriteError::Checksum)
+        );
+
+        // A corrupt, partly programmed eFuse; even rewriting a byte with the value it
+        // already holds is refused
+        let mut partial = blank;
+        partial[0] = 0x01;
+        assert_eq!(
+            plan_efuse_write(&partial, 0, &efuse()),
+            Err(EfuseWriteError::NotBlank(0))
+        );
+        assert_eq!(plan_efuse_write(&partial, 1, &efuse()[1..]), Ok(efuse()));
+    }
+
+    #[test]
+    fn efuse_contents() {
+        assert!(Efuse::new([EFUSE_BLANK; EFUSE_LEN]).is_none());
+        assert_eq!(Efuse::new(efuse()).map(|e| e.led_mode()), Some(0x9d21));
+
+        let mut with_mac = efuse();
+        with_mac[EFUSE_NODE_ID..EFUSE_NODE_ID + ETH_ALEN]
+            .copy_from_slice(&[0x00, 0x0e, 0xc6, 0x12, 0x34, 0x56]);
+        with_mac[EFUSE_LEN - 1] = 0;
+        with_mac[EFUSE_LEN - 1] = 0xff - efuse_checksum(&with_mac);
+        assert_eq!(
+            Efuse::new(with_mac).map(|e| e.node_id()),
+            Some([0x00, 0x0e, 0xc6, 0x12, 0x34, 0x56])
+        );
+    }
+}
@@ -0,0 +1,123 @@
+/*
+ * Module parameter support for ax88179_178a.
+ *
+ * The kernel's module_param() macro expands to a `struct kernel_param` in the
+ * `__param` section plus `parm=`/`parmtype=` strings in `.modinfo`, none of which
+ * bindgen can see, so the same layout is built here by hand.
+ */
+
+use core::cell::UnsafeCell;
+
+use linux_kernel_module::bindings::{kernel_param, kernel_param_ops, kstrtoint, param_get_int};
+use linux_kernel_module::c_types::{c_char, c_int};
+use linux_kernel_module::Error;
+
+/// An `int` module parameter which rejects values outside `min..=max` at load time.
+///
+/// The value is only written by the kernel's parameter parser before the module's init
+/// runs, and the parameters are registered without sysfs permissions, so reads never
+/// race with writes.
+#[repr(C)]
+pub(crate) struct IntParam {
+    // Must stay first, param_get_int reads it through the kernel_param's arg pointer
+    value: UnsafeCell<c_int>,
+    min: c_int,
+    max: c_int,
+}
+
+unsafe impl Sync for IntParam {}
+
+impl IntParam {
+    pub(crate) const fn new(default: c_int, min: c_int, max: c_int) -> Self {
+        Self {
+            value: UnsafeCell::new(default),
+            min,
+            max,
+        }
+    }
+
+    pub(crate) fn get(&self) -> c_int {
+        unsafe { core::ptr::read_volatile(self.value.get()) }
+    }
+}
+
+unsafe extern "C" fn int_param_set(val: *const c_char, kp: *const kernel_param) -> c_int {
+    let param = &*((*kp).__bindgen_anon_1.arg as *const IntParam);
+    let mut value: c_int = 0;
+
+    let ret = kstrtoint(val, 0, &mut value);
+    if ret != 0 {
+        return ret;
+    }
+
+    if value < param.min || value > param.max {
+        pr_err!(
+            "module parameter value {value} out of range {}..={}",
+            param.min,
+            param.max
+        );
+        return Error::EINVAL.to_kernel_errno();
+    }
+
+    *param.value.get() = value;
+    0
+}
+
+pub(crate) static INT_PARAM_OPS: kernel_param_ops = kernel_param_ops {
+    flags: 0,
+    set: Some(int_param_set),
+    get: Some(param_get_int),
+    free: None,
+};
+
+/// Emits a NUL-terminated `key=value` string into the `.modinfo` section.
+macro_rules! modinfo {
+    ($info:expr) => {
+        const _: () = {
+            const INFO: &[u8] = concat!($info, "\0").as_bytes();
+
+            #[link_section = ".modinfo"]
+            #[used]
+            static MODINFO: [u8; INFO.len()] = {
+                let mut bytes = [0u8; INFO.len()];
+                let mut i = 0;
+                while i < INFO.len() {
+                    bytes[i] = INFO[i];
+                    i += 1;
+                }
+                bytes
+            };
+        };
+    };
+}
+
+/// Declares an `int` module parameter `$name` with a default and an inclusive range,
+/// equivalent to `module_param($name, int, 0)` plus `MODULE_PARM_DESC`.
+macro_rules! module_param {
+    ($name:ident = $default:literal, $min:literal..=$max:literal, $desc:literal) => {
+        #[allow(non_upper_case_globals)]
+        static $name: $crate::params::IntParam =
+            $crate::params::IntParam::new($default, $min, $max);
+
+        const _: () = {
+            #[link_section = "__param"]
+            #[used]
+            static PARAM: $crate::SyncStatic<linux_kernel_module::bindings::kernel_param> =
+                $crate::SyncStatic(linux_kernel_module::bindings::kernel_param {
+                    name: concat!(stringify!($name), "\0").as_ptr() as _,
+                    // Only used to pin the module while sysfs writes the parameter
+                    mod_: core::ptr::null_mut(),
+                    ops: &$crate::params::INT_PARAM_OPS,
+                    perm: 0,
+                    level: -1,
+                    flags: 0,
+                    __bindgen_anon_1: linux_kernel_module::bindings::kernel_param__bindgen_ty_1 {
+                        arg: &$name as *const $crate::params::IntParam as *mut _,
+                    },
+                });
+        };
+
+        modinfo!(concat!("parm=", stringify!($name), ":", $desc));
+        modinfo!(concat!("parmtype=", stringify!($name), ":int"));
+    };
+}
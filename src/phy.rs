@@ -0,0 +1,294 @@
+/*
+ * Access to the AX88179 GigaPHY registers.
+ *
+ * Most of the PHY's vendor registers are banked behind GMII_PHY_PAGE_SELECT. Selecting
+ * a page hands out a guard which is the only way to reach that page's registers, and
+ * which switches the PHY back to page 0 when dropped, even on early return. Page 0
+ * registers, including the Clause 45 MMD window, are reached through `Phy` itself,
+ * which can't happen while a page guard is alive.
+ *
+ * A `Phy` holds the adapter's PHY lock for as long as it lives, so that no one else's
+ * accesses land on the wrong page or in the middle of an MMD access.
+ */
+
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::mem::{size_of, transmute};
+
+use linux_kernel_module::bindings::{
+    __mutex_init, lock_class_key, mutex, mutex_lock, mutex_unlock, usb_autopm_get_interface,
+    usb_autopm_put_interface,
+};
+use linux_kernel_module::Error;
+
+use crate::error::DriverResult;
+use crate::{
+    Ax88179, CmdCtx, SyncStatic, AX88179_PHY_ID, AX_ACCESS_PHY, GMII_LED_ACTIVE, GMII_LED_LINK,
+    GMII_PHYPAGE, GMII_PHY_MAADR, GMII_PHY_MACR, GMII_PHY_MACR_DATA, GMII_PHY_PAGE_SELECT,
+    GMII_PHY_PAGE_SELECT_EXT, GMII_PHY_PAGE_SELECT_PAGE0, GMII_PHY_PAGE_SELECT_PAGE3,
+    GMII_PHY_PAGE_SELECT_PAGE5, GMII_PHY_PHYSR,
+};
+
+/// A register which is only visible while page `P` is selected.
+pub(crate) struct PhyReg<P> {
+    addr: u16,
+    _page: PhantomData<P>,
+}
+
+impl<P> PhyReg<P> {
+    const fn new(addr: u16) -> Self {
+        Self {
+            addr,
+            _page: PhantomData,
+        }
+    }
+}
+
+// Can't derive these without requiring P: Clone
+impl<P> Clone for PhyReg<P> {
+    fn clone(&self) -> Self {
+        Self::new(self.addr)
+    }
+}
+
+impl<P> Copy for PhyReg<P> {}
+
+pub(crate) trait PhyPage {
+    /// Switches the PHY to this page.
+    unsafe fn select(phy: &Phy) -> DriverResult<()>;
+}
+
+/// The standard MII register set.
+pub(crate) struct Page0;
+
+/// Green ethernet control.
+pub(crate) struct Page3;
+
+/// Green ethernet tuning.
+pub(crate) struct Page5;
+
+/// Extension page 0x2c, the LED configuration.
+pub(crate) struct ExtPage2c;
+
+impl PhyPage for Page0 {
+    unsafe fn select(phy: &Phy) -> DriverResult<()> {
+        phy.raw_write(GMII_PHY_PAGE_SELECT, GMII_PHY_PAGE_SELECT_PAGE0)
+    }
+}
+
+impl PhyPage for Page3 {
+    unsafe fn select(phy: &Phy) -> DriverResult<()> {
+        phy.raw_write(GMII_PHY_PAGE_SELECT, GMII_PHY_PAGE_SELECT_PAGE3)
+    }
+}
+
+impl PhyPage for Page5 {
+    unsafe fn select(phy: &Phy) -> DriverResult<()> {
+        phy.raw_write(GMII_PHY_PAGE_SELECT, GMII_PHY_PAGE_SELECT_PAGE5)
+    }
+}
+
+impl PhyPage for ExtPage2c {
+    unsafe fn select(phy: &Phy) -> DriverResult<()> {
+        phy.raw_write(GMII_PHY_PAGE_SELECT, GMII_PHY_PAGE_SELECT_EXT)?;
+        phy.raw_write(GMII_PHYPAGE, 0x2c)
+    }
+}
+
+pub(crate) const PHY_BMCR: PhyReg<Page0> = PhyReg::new(0x00);
+pub(crate) const PHY_ADVERTISE: PhyReg<Page0> = PhyReg::new(0x04);
+pub(crate) const PHY_CTRL1000: PhyReg<Page0> = PhyReg::new(0x09);
+pub(crate) const PHY_MACR: PhyReg<Page0> = PhyReg::new(GMII_PHY_MACR);
+pub(crate) const PHY_MAADR: PhyReg<Page0> = PhyReg::new(GMII_PHY_MAADR);
+pub(crate) const PHY_PHYSR: PhyReg<Page0> = PhyReg::new(GMII_PHY_PHYSR);
+
+pub(crate) const PAGE3_GETH_CTRL: PhyReg<Page3> = PhyReg::new(25);
+pub(crate) const PAGE5_GETH_TUNE: PhyReg<Page5> = PhyReg::new(1);
+
+pub(crate) const EXT2C_LED_ACTIVE: PhyReg<ExtPage2c> = PhyReg::new(GMII_LED_ACTIVE);
+pub(crate) const EXT2C_LED_LINK: PhyReg<ExtPage2c> = PhyReg::new(GMII_LED_LINK);
+
+const MUTEX_ZEROED: mutex = unsafe { transmute([0u8; size_of::<mutex>()]) };
+
+// The lockdep class of every adapter's PHY lock, mutex_init is a macro which makes one
+static PHY_LOCK_KEY: SyncStatic<UnsafeCell<lock_class_key>> = SyncStatic(UnsafeCell::new(unsafe {
+    transmute([0u8; size_of::<lock_class_key>()])
+}));
+
+/// One adapter's PHY lock, taken by `Phy`.
+pub(crate) struct PhyLock(UnsafeCell<mutex>);
+
+unsafe impl Send for PhyLock {}
+unsafe impl Sync for PhyLock {}
+
+impl PhyLock {
+    pub(crate) const fn new() -> Self {
+        Self(UnsafeCell::new(MUTEX_ZEROED))
+    }
+
+    /// # Safety
+    /// Must be called once the lock is at its final address, before it's first taken.
+    pub(crate) unsafe fn init(&self) {
+        __mutex_init(
+            self.0.get(),
+            "&ax88179->phy_lock\0".as_ptr() as _,
+            PHY_LOCK_KEY.0.get(),
+        );
+    }
+}
+
+/// The AX88179's internal PHY, normally sitting on page 0.
+pub(crate) struct Phy<'a> {
+    ctx: CmdCtx,
+    lock: &'a PhyLock,
+}
+
+impl<'a> Phy<'a> {
+    /// Takes the PHY of `ax`, waiting for anyone else using it.
+    ///
+    /// # Safety
+    /// `ax` must belong to a bound AX88179 usbnet device.
+    pub(crate) unsafe fn new(ax: &'a Ax88179) -> DriverResult<Self> {
+        Self::with_ctx(ax, CmdCtx::new(ax.dev)?)
+    }
+
+    /// Takes the PHY of `ax` and reaches it through `ctx`, so also from within the PM
+    /// callbacks.
+    ///
+    /// Outside of those the device is resumed first, and kept from suspending until
+    /// the `Phy` is dropped. Otherwise the PM callbacks, which need the PHY too, could
+    /// end up waiting for the lock while its owner waits for them.
+    ///
+    /// # Safety
+    /// As for `new`, with `ctx` made for the same device.
+    pub(crate) unsafe fn with_ctx(ax: &'a Ax88179, ctx: CmdCtx) -> DriverResult<Self> {
+        if !ctx.in_pm {
+            let ret = usb_autopm_get_interface((*ctx.dev).intf);
+            if ret < 0 {
+                return Err(Error::from_kernel_errno(ret).into());
+            }
+        }
+
+        mutex_lock(ax.phy_lock.0.get());
+
+        Ok(Self {
+            ctx,
+            lock: &ax.phy_lock,
+        })
+    }
+
+    /// The vendor command context the PHY is reached through, for the MAC registers
+    /// which go along with some PHY settings.
+    pub(crate) fn ctx(&self) -> &CmdCtx {
+        &self.ctx
+    }
+
+    unsafe fn raw_read(&self, addr: u16) -> DriverResult<u16> {
+        let mut val: u16 = 0;
+        self.ctx.read(
+            AX_ACCESS_PHY,
+            AX88179_PHY_ID,
+            addr,
+            2,
+            &mut val as *mut u16 as _,
+            1,
+        )?;
+        Ok(val)
+    }
+
+    unsafe fn raw_write(&self, addr: u16, val: u16) -> DriverResult<()> {
+        self.ctx.write(AX_ACCESS_PHY, AX88179_PHY_ID, addr, val)
+    }
+
+    pub(crate) fn read(&mut self, reg: PhyReg<Page0>) -> DriverResult<u16> {
+        unsafe { self.raw_read(reg.addr) }
+    }
+
+    pub(crate) fn write(&mut self, reg: PhyReg<Page0>, val: u16) -> DriverResult<()> {
+        unsafe { self.raw_write(reg.addr, val) }
+    }
+
+    /// Selects page `P` until the returned guard is dropped.
+    pub(crate) fn page<P: PhyPage>(&mut self) -> DriverResult<PageGuard<'_, 'a, P>> {
+        unsafe {
+            if let Err(e) = P::select(self) {
+                // Don't leave the PHY half way through a selection
+                let _ = Page0::select(self);
+                return Err(e);
+            }
+        }
+
+        Ok(PageGuard {
+            phy: self,
+            _page: PhantomData,
+        })
+    }
+
+    /// Points the Clause 22 MAADR register at a Clause 45 MMD register.
+    fn mmd_select(&mut self, devad: u16, reg: u16) -> DriverResult<()> {
+        self.write(PHY_MACR, devad)?;
+        self.write(PHY_MAADR, reg)?;
+        self.write(PHY_MACR, GMII_PHY_MACR_DATA | devad)
+    }
+
+    pub(crate) fn mmd_read(&mut self, devad: u16, reg: u16) -> DriverResult<u16> {
+        self.mmd_select(devad, reg)?;
+        self.read(PHY_MAADR)
+    }
+
+    pub(crate) fn mmd_write(&mut self, devad: u16, reg: u16, val: u16) -> DriverResult<()> {
+        self.mmd_select(devad, reg)?;
+        self.write(PHY_MAADR, val)
+    }
+
+    /// Reads a register for the MII library, which addresses the PHY by ID and only
+    /// knows about page 0.
+    pub(crate) fn mii_read(&mut self, phy_id: u16, loc: u16) -> DriverResult<u16> {
+        let mut val: u16 = 0;
+        unsafe {
+            self.ctx
+                .read(AX_ACCESS_PHY, phy_id, loc, 2, &mut val as *mut u16 as _, 1)?;
+        }
+        Ok(val)
+    }
+
+    /// Writes a register for the MII library, see `mii_read`.
+    pub(crate) fn mii_write(&mut self, phy_id: u16, loc: u16, val: u16) -> DriverResult<()> {
+        self.ctx.write(AX_ACCESS_PHY, phy_id, loc, val)
+    }
+}
+
+impl Drop for Phy<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            mutex_unlock(self.lock.0.get());
+
+            if !self.ctx.in_pm {
+                usb_autopm_put_interface((*self.ctx.dev).intf);
+            }
+        }
+    }
+}
+
+/// Page `P` of the PHY is selected for as long as this lives.
+pub(crate) struct PageGuard<'a, 'p, P: PhyPage> {
+    phy: &'a mut Phy<'p>,
+    _page: PhantomData<P>,
+}
+
+impl<P: PhyPage> PageGuard<'_, '_, P> {
+    pub(crate) fn read(&self, reg: PhyReg<P>) -> DriverResult<u16> {
+        unsafe { self.phy.raw_read(reg.addr) }
+    }
+
+    pub(crate) fn write(&self, reg: PhyReg<P>, val: u16) -> DriverResult<()> {
+        unsafe { self.phy.raw_write(reg.addr, val) }
+    }
+}
+
+impl<P: PhyPage> Drop for PageGuard<'_, '_, P> {
+    fn drop(&mut self) {
+        // Nothing more can be done if this fails, the next selection will try again
+        let _ = unsafe { Page0::select(self.phy) };
+    }
+}
@@ -0,0 +1,235 @@
+/*
+ * Per-adapter tuning knobs under /sys/class/net/<iface>/ax88179/.
+ *
+ * Each attribute mirrors one of the module parameters, but only affects the adapter
+ * it belongs to. New values are stored in Ax88179 and take effect on the next
+ * link_reset, i.e. the next time the link comes up.
+ */
+
+use core::fmt::Write;
+use core::mem::{size_of, transmute, MaybeUninit};
+use core::sync::atomic::Ordering;
+
+use linux_kernel_module::bindings::{
+    attribute, attribute_group, device, device_attribute, kstrtoint, net_device,
+};
+use linux_kernel_module::c_types::{c_char, c_int};
+use linux_kernel_module::Error;
+
+use crate::net::NetDevice;
+use crate::{Ax88179, SyncStatic};
+
+// sysfs hands show() a PAGE_SIZE buffer
+const PAGE_SIZE: usize = 4096;
+
+const ATTRIBUTE_ZEROED: attribute = unsafe { transmute([0u8; size_of::<attribute>()]) };
+const DEVICE_ATTRIBUTE_ZEROED: device_attribute =
+    unsafe { transmute([0u8; size_of::<device_attribute>()]) };
+const ATTRIBUTE_GROUP_ZEROED: attribute_group =
+    unsafe { transmute([0u8; size_of::<attribute_group>()]) };
+
+// S_IRUGO | S_IWUSR
+const ATTR_MODE_RW: u16 = 0o644;
+
+/// container_of(dev, struct net_device, dev), `to_net_dev` is a macro
+unsafe fn to_net_dev(dev: *mut device) -> *mut net_device {
+    let uninit = MaybeUninit::<net_device>::uninit();
+    let base = uninit.as_ptr();
+    let offset = core::ptr::addr_of!((*base).dev) as usize - base as usize;
+
+    (dev as *mut u8).sub(offset) as _
+}
+
+/// The driver state behind a netdev's sysfs attribute. It is only known to be alive
+/// while sysfs is calling into the attribute, so it borrows from `dev`.
+unsafe fn attr_data(dev: &device) -> &Ax88179 {
+    let net = NetDevice::from_raw(to_net_dev(dev as *const device as *mut device));
+    Ax88179::of(net.usbnet().as_ptr())
+}
+
+/// fmt::Write over the page sysfs gives to show()
+struct PageWriter {
+    buf: *mut u8,
+    len: usize,
+}
+
+impl Write for PageWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        // Leave room for the terminating NUL
+        if self.len + s.len() >= PAGE_SIZE {
+            return Err(core::fmt::Error);
+        }
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(s.as_ptr(), self.buf.add(self.len), s.len());
+            self.len += s.len();
+            *self.buf.add(self.len) = 0;
+        }
+
+        Ok(())
+    }
+}
+
+unsafe fn show_int(buf: *mut c_char, value: c_int) -> isize {
+    let mut writer = PageWriter {
+        buf: buf as *mut u8,
+        len: 0,
+    };
+
+    match writeln!(writer, "{value}") {
+        Ok(()) => writer.len as isize,
+        Err(_) => Error::EINVAL.to_kernel_errno() as isize,
+    }
+}
+
+unsafe fn parse_int(buf: *const c_char, min: c_int, max: c_int) -> Result<c_int, isize> {
+    let mut value: c_int = 0;
+
+    let ret = kstrtoint(buf, 0, &mut value);
+    if ret != 0 {
+        return Err(ret as isize);
+    }
+
+    if value < min || value > max {
+        return Err(Error::EINVAL.to_kernel_errno() as isize);
+    }
+
+    Ok(value)
+}
+
+unsafe extern "C" fn rx_bulkin_qsize_show(
+    dev: *mut device,
+    _attr: *mut device_attribute,
+    buf: *mut c_char,
+) -> isize {
+    show_int(buf, attr_data(&*dev).bsize.load(Ordering::Relaxed))
+}
+
+unsafe extern "C" fn rx_bulkin_qsize_store(
+    dev: *mut device,
+    _attr: *mut device_attribute,
+    buf: *const c_char,
+    count: usize,
+) -> isize {
+    match parse_int(buf, -1, 24) {
+        Ok(value) => {
+            attr_data(&*dev).bsize.store(value, Ordering::Relaxed);
+            count as isize
+        }
+        Err(e) => e,
+    }
+}
+
+unsafe extern "C" fn rx_bulkin_ifg_show(
+    dev: *mut device,
+    _attr: *mut device_attribute,
+    buf: *mut c_char,
+) -> isize {
+    show_int(buf, attr_data(&*dev).ifg.load(Ordering::Relaxed))
+}
+
+unsafe extern "C" fn rx_bulkin_ifg_store(
+    dev: *mut device,
+    _attr: *mut device_attribute,
+    buf: *const c_char,
+    count: usize,
+) -> isize {
+    match parse_int(buf, -1, 255) {
+        Ok(value) => {
+            attr_data(&*dev).ifg.store(value, Ordering::Relaxed);
+            count as isize
+        }
+        Err(e) => e,
+    }
+}
+
+unsafe extern "C" fn eee_show(
+    dev: *mut device,
+    _attr: *mut device_attribute,
+    buf: *mut c_char,
+) -> isize {
+    show_int(buf, attr_data(&*dev).eee.load(Ordering::Relaxed) as c_int)
+}
+
+unsafe extern "C" fn eee_store(
+    dev: *mut device,
+    _attr: *mut device_attribute,
+    buf: *const c_char,
+    count: usize,
+) -> isize {
+    match parse_int(buf, 0, 1) {
+        Ok(value) => {
+            let data = attr_data(&*dev);
+            data.eee.store(value != 0, Ordering::Relaxed);
+            data.phy_settings_changed.store(true, Ordering::Release);
+            count as isize
+        }
+        Err(e) => e,
+    }
+}
+
+unsafe extern "C" fn green_ethernet_show(
+    dev: *mut device,
+    _attr: *mut device_attribute,
+    buf: *mut c_char,
+) -> isize {
+    show_int(buf, attr_data(&*dev).geth.load(Ordering::Relaxed) as c_int)
+}
+
+unsafe extern "C" fn green_ethernet_store(
+    dev: *mut device,
+    _attr: *mut device_attribute,
+    buf: *const c_char,
+    count: usize,
+) -> isize {
+    match parse_int(buf, 0, 1) {
+        Ok(value) => {
+            let data = attr_data(&*dev);
+            data.geth.store(value != 0, Ordering::Relaxed);
+            data.phy_settings_changed.store(true, Ordering::Release);
+            count as isize
+        }
+        Err(e) => e,
+    }
+}
+
+macro_rules! device_attr_rw {
+    ($attr:ident, $show:ident, $store:ident) => {
+        #[allow(non_upper_case_globals)]
+        static $attr: SyncStatic<device_attribute> = SyncStatic(device_attribute {
+            attr: attribute {
+                name: concat!(stringify!($attr), "\0").as_ptr() as _,
+                mode: ATTR_MODE_RW,
+                ..ATTRIBUTE_ZEROED
+            },
+            show: Some($show),
+            store: Some($store),
+            ..DEVICE_ATTRIBUTE_ZEROED
+        });
+    };
+}
+
+device_attr_rw!(rx_bulkin_qsize, rx_bulkin_qsize_show, rx_bulkin_qsize_store);
+device_attr_rw!(rx_bulkin_ifg, rx_bulkin_ifg_show, rx_bulkin_ifg_store);
+device_attr_rw!(eee, eee_show, eee_store);
+device_attr_rw!(green_ethernet, green_ethernet_show, green_ethernet_store);
+
+static AX88179_ATTRS: SyncStatic<[*const attribute; 5]> = SyncStatic([
+    &rx_bulkin_qsize.0.attr,
+    &rx_bulkin_ifg.0.attr,
+    &eee.0.attr,
+    &green_ethernet.0.attr,
+    core::ptr::null(),
+]);
+
+static AX88179_ATTR_GROUP: SyncStatic<attribute_group> = SyncStatic(attribute_group {
+    name: "ax88179\0".as_ptr() as _,
+    attrs: &AX88179_ATTRS.0 as *const _ as *mut _,
+    ..ATTRIBUTE_GROUP_ZEROED
+});
+
+/// Hooks the tuning attributes into the netdev. Must be called from bind, before usbnet
+/// registers the netdev, which creates and removes the group along with it.
+pub(crate) unsafe fn ax88179_add_sysfs_group(net: *mut net_device) {
+    (*net).sysfs_groups[0] = &AX88179_ATTR_GROUP.0;
+}